@@ -0,0 +1,51 @@
+// ? Wall-clock benchmarks: every `benches/programs/*.lox` script runs a few
+// ? times on each backend and the median is reported. Run with `cargo bench`,
+// ? optionally followed by a substring of the program names to run
+// ?   cargo bench -- fib
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crafting_interpreters::{Backend, Lox};
+
+const RUNS : usize = 5;
+
+// * Median time of `RUNS` runs of `source`, each on a fresh `Lox`
+fn measure (source : &str, backend : Backend) -> Duration {
+    let mut times = (0..RUNS).map(|_| {
+        let mut lox = Lox::with_io(Box::new(std::io::sink()), Box::new(std::io::empty()));
+        lox.backend = backend;
+        let start = Instant::now();
+        let result = lox.run(source);
+        let elapsed = start.elapsed();
+        assert!(result.is_ok(), "{:?}", result);
+        elapsed
+    }).collect::<Vec<Duration>>();
+    times.sort();
+    times[RUNS / 2]
+}
+
+fn main () {
+    // `cargo bench` passes `--bench`; anything else filters by name
+    let filter = std::env::args().skip(1).find(|a| !a.starts_with("--"));
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("benches").join("programs");
+    let mut programs = fs::read_dir(&dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "lox"))
+        .collect::<Vec<_>>();
+    programs.sort();
+
+    println!("{:<16} {:>12} {:>12}", "program", "tree", "vm");
+    for path in programs {
+        let name = path.file_stem().unwrap().to_string_lossy().to_string();
+        if filter.as_ref().is_some_and(|f| !name.contains(f.as_str())) {
+            continue;
+        }
+        let source = fs::read_to_string(&path).unwrap();
+        let tree = measure(&source, Backend::TreeWalk);
+        let vm = measure(&source, Backend::Vm);
+        println!("{:<16} {:>10.2}ms {:>10.2}ms", name, tree.as_secs_f64() * 1000.0, vm.as_secs_f64() * 1000.0);
+    }
+}
@@ -16,8 +16,10 @@ def defineAst(outputDir, baseName : str, types : list[str]):
 
     with open(p, "x") as f:
         if baseName == "Stmt": f.write("use crate::expr::Expr;\n")
-        f.write("use crate::scanner::{Token, LiteralType};\n")
-        if baseName == "Expr": f.write("use std::hash::Hash;\n")
+        if baseName == "Stmt": f.write("use crate::scanner::{Token, Span};\n")
+        if baseName == "Expr": f.write("use crate::scanner::{Token, LiteralType, Span};\n")
+        if baseName == "Expr": f.write("use crate::stmt::Function;\n")
+        if baseName == "Expr": f.write("use crate::parser::NodeId;\n")
     
         defineEnum(f, baseName, [t.split(":")[0] for t in types])
 
@@ -29,11 +31,6 @@ def defineAst(outputDir, baseName : str, types : list[str]):
         defineVistorTrait(f, [t.split(":")[0] for t in types])
         defineAccept(f, baseName, [t.split(":")[0] for t in types])
 
-        if baseName == "Expr":
-            define_hash(f, baseName)
-            define_partial_eq(f, baseName)
-            define_eq(f, baseName)
-
         f.write("\n")
 
 def defineEnum (fileHandler, enumName : str, cases : list[str]) :
@@ -58,8 +55,11 @@ def defineStruct (fileHandelr, base_class, structName, fields) :
         ### construct struct fields
         fileHandelr.write(f"    pub {name_of_field} : {type_of_field},\n")
     
-    # add uuid field
-    if base_class == "Expr": fileHandelr.write(f"    pub uuid : usize\n")
+    # every node remembers the source range it was parsed from
+    fileHandelr.write(f"    pub span : Span,\n")
+
+    # expressions are told apart by the id the parser gave them
+    if base_class == "Expr": fileHandelr.write(f"    pub id : NodeId\n")
 
     fileHandelr.write("}\n")
 
@@ -81,37 +81,27 @@ def defineAccept (fileHander, forType : str, types : list[str]) :
     fileHander.write("          }\n")
     fileHander.write("      }\n")
     
+    fileHander.write(f"    pub fn span(&self) -> Span {{\n")
+    fileHander.write(f"        match self {{\n")
+    for t in types:
+        typeName = t.split(":")[0].strip()
+        fileHander.write(f"            {forType}::{typeName} (e) => e.span,\n")
+    fileHander.write("          }\n")
+    fileHander.write("      }\n")
+
     if forType == "Expr": 
 
-        fileHander.write(f"    pub fn get_uuid(&self) -> usize {{\n")
+        fileHander.write(f"    pub fn id(&self) -> NodeId {{\n")
         fileHander.write(f"        match self {{\n")
         for t in types:
             typeName = t.split(":")[0].strip()
-            fileHander.write(f"            {forType}::{typeName} (e) => e.uuid,\n")
+            fileHander.write(f"            {forType}::{typeName} (e) => e.id,\n")
         fileHander.write("          }\n")
         fileHander.write("      }\n")
     fileHander.write("}\n")
 
 
 
-def define_hash (fileHandler, forType : str) :
-    fileHandler.write(f"impl Hash for {forType} {{\n")
-    fileHandler.write(f"    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {{\n")
-    fileHandler.write(f"        self.get_uuid().hash(state);\n")
-    fileHandler.write(f"    }}\n")
-    fileHandler.write(f"}}\n")
-
-def define_partial_eq (fileHandler, forType : str) :
-    fileHandler.write(f"impl PartialEq for {forType} {{\n")
-    fileHandler.write(f"    fn eq(&self, other: &Self) -> bool {{\n")
-    fileHandler.write(f"        self.get_uuid() == other.get_uuid()\n")
-    fileHandler.write(f"    }}\n")
-    fileHandler.write(f"}}\n")
-
-def define_eq (fileHandler, forType : str) :
-    fileHandler.write(f"impl Eq for {forType} {{\n")
-    fileHandler.write(f"}}\n")
-
 if __name__ == "__main__" :
     exprs = [
         "Binary   : Expr left, Token operator, Expr right",
@@ -127,6 +117,11 @@ if __name__ == "__main__" :
         "Conditional : Expr condition, Expr then_branch, Expr else_branch",
         "Variable : Token name",
         "Assigment : Token name, Expr value",
+        "List     : Token bracket, Vec<Expr> elements",
+        "Index    : Expr object, Token bracket, Expr index",
+        "SetIndex : Expr object, Token bracket, Expr index, Expr value",
+        "Map      : Token brace, Vec<Expr> keys, Vec<Expr> values",
+        "Lambda   : Function declaration",
     ]
 
     # the token in the function call is used to report optional runtaime errors
@@ -138,11 +133,14 @@ if __name__ == "__main__" :
         "Var        : Token name, Option<Expr> initializer",
         "Block      : Vec<Stmt> statements",
         "Iff         : Expr condition, Box<Stmt> then_branch, Option<Box<Stmt>> else_branch",
-        "Whilee     : Expr condition, Box<Stmt> body, bool is_for",
-        "Breakk      : Token keyword",
-        "Continuee   : Token keyword",
+        "Whilee     : Option<Token> label, Expr condition, Box<Stmt> body, Option<Expr> increment",
+        "ForIn      : Option<Token> label, Token name, Token keyword, Expr iterable, Box<Stmt> body",
+        "Breakk      : Token keyword, Option<Token> label",
+        "Continuee   : Token keyword, Option<Token> label",
         "Returnn     : Token keyword, Option<Expr> value",
-        "Class       : Token name, Option<Expr> SuperClass, Vec<Function> methods"
+        "Class       : Token name, Option<Expr> super_class, Vec<Function> methods",
+        "Throw       : Token keyword, Expr value",
+        "Tryy        : Token keyword, Vec<Stmt> body, Option<Token> catch_name, Option<Vec<Stmt>> catch_body, Option<Vec<Stmt>> finally_body",
 
     ]
     if len(sys.argv) != 2:
@@ -150,4 +148,5 @@ if __name__ == "__main__" :
         sys.exit(64)
 
     outputDir = sys.argv[1]
-    defineAst(outputDir, "Expr", exprs)
\ No newline at end of file
+    defineAst(outputDir, "Expr", exprs)
+    defineAst(outputDir, "Stmt", smts)
\ No newline at end of file
@@ -0,0 +1,138 @@
+// ? Bytecode for the `vm` backend: the `Compiler` turns each function into a
+// ? `Proto` whose `Chunk` holds the instructions, the source span each one
+// ? reports errors at, and the constants, names and nested functions they use
+
+use std::rc::Rc;
+
+use crate::scanner::{LiteralType, Span, TokenType};
+use crate::symbol::Symbol;
+
+// ? Operands index into the chunk's tables or the frame's slots; jump
+// ? targets are absolute instruction indices
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Constant (usize),
+    Nil,
+    True,
+    False,
+    Pop,
+
+    GetLocal (usize),
+    SetLocal (usize),
+    GetUpvalue (usize),
+    SetUpvalue (usize),
+    GetGlobal (usize),
+    DefineGlobal (usize),
+    SetGlobal (usize),
+
+    GetProperty (usize),
+    SetProperty (usize),
+    // * pops the superclass and `this`, pushes the bound superclass method
+    GetSuper (usize),
+    Index,
+    SetIndex,
+
+    // * arithmetic, comparison and equality, with the interpreter's semantics
+    Binary (TokenType),
+    Not,
+    Negate,
+
+    Print,
+    // * prints the top of the stack without popping it (REPL echo)
+    Echo,
+
+    Jump (usize),
+    // * neither pops the condition
+    JumpIfFalse (usize),
+    JumpIfTrue (usize),
+
+    // * argument count
+    Call (usize),
+    // * index into `functions`, the new closure captures its upvalues
+    Closure (usize),
+    // * closes the upvalue over the top slot, then pops it
+    CloseUpvalue,
+    Return,
+
+    Class (usize),
+    // * copies the superclass's methods into the class on top, then pops it
+    Inherit,
+    Method (usize),
+
+    // * element count, and entry count
+    List (usize),
+    Map (usize),
+
+    Throw,
+    // * re-raises the error a finally-only handler set aside
+    Rethrow,
+    // * errors raised until the matching `PopHandler` jump to `target`. A
+    // * `rethrow` handler keeps the error for `Rethrow` instead of pushing it
+    PushHandler { target : usize, rethrow : bool },
+    PopHandler,
+
+    // * turns the popped value into an iterator on the iterator stack
+    IterStart,
+    // * pushes the next value, or jumps to the operand once exhausted
+    IterNext (usize),
+    PopIter,
+}
+
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code : Vec<Op>,
+    pub spans : Vec<Span>,
+    pub constants : Vec<LiteralType>,
+    pub names : Vec<Symbol>,
+    pub functions : Vec<Rc<Proto>>,
+}
+
+impl Chunk {
+    // * Appends `op`, returning its index for later patching
+    pub fn emit (&mut self, op : Op, span : Span) -> usize {
+        self.code.push(op);
+        self.spans.push(span);
+        self.code.len() - 1
+    }
+
+    pub fn constant (&mut self, value : LiteralType) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+
+    pub fn name (&mut self, name : &Symbol) -> usize {
+        match self.names.iter().position(|n| n == name) {
+            Some(i) => i,
+            None => {
+                self.names.push(name.clone());
+                self.names.len() - 1
+            }
+        }
+    }
+}
+
+// ? Where a closure finds a captured variable when it is created: a slot of
+// ? the enclosing frame, or one of the enclosing closure's own upvalues
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UpvalueRef {
+    pub is_local : bool,
+    pub index : usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+// ? A compiled function, shared by every closure made from it
+#[derive(Debug)]
+pub struct Proto {
+    pub name : String,
+    pub arity : usize,
+    pub kind : FunctionKind,
+    pub upvalues : Vec<UpvalueRef>,
+    pub chunk : Chunk,
+}
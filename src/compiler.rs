@@ -0,0 +1,702 @@
+// ? Compiles a resolved program into bytecode for the `vm` backend.
+// ? Locals live in stack slots numbered at compile time, variables captured
+// ? by closures become upvalues, and everything else is a global, which
+// ? mirrors how the `Resolver` splits them for the tree-walker
+
+use std::rc::Rc;
+
+use crate::chunk::{Chunk, FunctionKind, Op, Proto, UpvalueRef};
+use crate::expr::{self, Expr, Visitor as ExprVisitor};
+use crate::scanner::{LiteralType, Span, Token, TokenType};
+use crate::stmt::{self, Stmt, Visitor as StmtVisitor};
+use crate::symbol::Symbol;
+
+struct Local {
+    name : Symbol,
+    depth : usize,
+    captured : bool,
+}
+
+struct Loop {
+    label : Option<Symbol>,
+    // locals and try blocks that were open when the loop started
+    locals : usize,
+    tries : usize,
+    // for-in loops keep an iterator on the VM's iterator stack
+    iterates : bool,
+    // jumps patched once the loop's end or continue point is known
+    breaks : Vec<usize>,
+    continues : Vec<usize>,
+}
+
+// ? A try block (or a catch block guarded for its finally) whose handler is
+// ? still pushed; jumping out of it must pop the handler and run `finally`
+struct Try {
+    finally : Option<Vec<Stmt>>,
+}
+
+struct FunctionState {
+    proto : Proto,
+    locals : Vec<Local>,
+    scope_depth : usize,
+    loops : Vec<Loop>,
+    tries : Vec<Try>,
+}
+
+pub struct Compiler {
+    // innermost function last
+    functions : Vec<FunctionState>,
+}
+
+// ? The compiled program. `has_value` tells whether it ends in an expression
+// ? statement, whose value the script returns
+pub struct Script {
+    pub function : Rc<Proto>,
+    pub has_value : bool,
+}
+
+impl Compiler {
+    pub fn compile (statements : &[Stmt], repl : bool) -> Script {
+        let mut compiler = Compiler {
+            functions : vec![],
+        };
+        compiler.begin_function("script", FunctionKind::Script);
+
+        let mut has_value = false;
+        for (i, statement) in statements.iter().enumerate() {
+            if let Stmt::Expression(e) = statement {
+                compiler.expression(&e.expression);
+                if repl {
+                    compiler.emit(Op::Echo, e.span);
+                }
+                if i == statements.len() - 1 {
+                    compiler.emit(Op::Return, e.span);
+                    has_value = true;
+                } else {
+                    compiler.emit(Op::Pop, e.span);
+                }
+            } else {
+                compiler.statement(statement);
+            }
+        }
+        if !has_value {
+            compiler.emit(Op::Nil, Span::default());
+            compiler.emit(Op::Return, Span::default());
+        }
+
+        Script {
+            function : Rc::new(compiler.end_function()),
+            has_value,
+        }
+    }
+
+    // Helpers:
+
+    fn current (&mut self) -> &mut FunctionState {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn chunk (&mut self) -> &mut Chunk {
+        &mut self.current().proto.chunk
+    }
+
+    fn emit (&mut self, op : Op, span : Span) -> usize {
+        self.chunk().emit(op, span)
+    }
+
+    fn here (&mut self) -> usize {
+        self.chunk().code.len()
+    }
+
+    // * Points the jump at `at` to the next instruction
+    fn patch (&mut self, at : usize) {
+        let target = self.here();
+        match &mut self.chunk().code[at] {
+            Op::Jump(t) | Op::JumpIfFalse(t) | Op::JumpIfTrue(t) | Op::IterNext(t) => *t = target,
+            Op::PushHandler { target : t, .. } => *t = target,
+            op => unreachable!("{:?} has no jump target", op),
+        }
+    }
+
+    fn name (&mut self, name : &Symbol) -> usize {
+        self.chunk().name(name)
+    }
+
+    fn statement (&mut self, statement : &Stmt) {
+        statement.accept(self)
+    }
+
+    fn expression (&mut self, expression : &Expr) {
+        expression.accept(self)
+    }
+
+    fn begin_function (&mut self, name : &str, kind : FunctionKind) {
+        // slot 0 holds the callee, or the receiver inside methods
+        let receiver = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => "this",
+            _ => "",
+        };
+        self.functions.push(FunctionState {
+            proto : Proto {
+                name : name.to_string(),
+                arity : 0,
+                kind,
+                upvalues : vec![],
+                chunk : Chunk::default(),
+            },
+            locals : vec![Local { name : Symbol::intern(receiver), depth : 0, captured : false }],
+            scope_depth : 0,
+            loops : vec![],
+            tries : vec![],
+        });
+    }
+
+    fn end_function (&mut self) -> Proto {
+        self.functions.pop().unwrap().proto
+    }
+
+    fn function (&mut self, function : &stmt::Function, kind : FunctionKind) {
+        self.begin_function(&function.name.lexeme, kind);
+        self.current().proto.arity = function.params.len();
+        self.current().scope_depth = 1;
+        for param in &function.params {
+            self.add_local(&param.lexeme);
+        }
+        for statement in &function.body {
+            self.statement(statement);
+        }
+        self.implicit_return(function.span);
+
+        let proto = self.end_function();
+        let index = self.chunk().functions.len();
+        self.chunk().functions.push(Rc::new(proto));
+        self.emit(Op::Closure(index), function.span);
+    }
+
+    fn implicit_return (&mut self, span : Span) {
+        if self.current().proto.kind == FunctionKind::Initializer {
+            self.emit(Op::GetLocal(0), span);
+        } else {
+            self.emit(Op::Nil, span);
+        }
+        self.emit(Op::Return, span);
+    }
+
+    fn begin_scope (&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    fn end_scope (&mut self, span : Span) {
+        self.current().scope_depth -= 1;
+        let depth = self.current().scope_depth;
+        while self.current().locals.last().is_some_and(|l| l.depth > depth) {
+            let local = self.current().locals.pop().unwrap();
+            self.emit(if local.captured { Op::CloseUpvalue } else { Op::Pop }, span);
+        }
+    }
+
+    // * Discards the locals above `count` without forgetting them, for jumps
+    // * that leave their scopes early
+    fn discard_locals (&mut self, count : usize, span : Span) {
+        let captured = self.current().locals[count..].iter().rev().map(|l| l.captured).collect::<Vec<bool>>();
+        for captured in captured {
+            self.emit(if captured { Op::CloseUpvalue } else { Op::Pop }, span);
+        }
+    }
+
+    fn block (&mut self, statements : &[Stmt], span : Span) {
+        self.begin_scope();
+        for statement in statements {
+            self.statement(statement);
+        }
+        self.end_scope(span);
+    }
+
+    // * The value on top of the stack becomes the local `name`
+    fn add_local (&mut self, name : &Symbol) {
+        let depth = self.current().scope_depth;
+        self.current().locals.push(Local {
+            name : name.clone(),
+            depth,
+            captured : false,
+        });
+    }
+
+    // * Binds the value on top of the stack to a new variable in the current scope
+    fn define (&mut self, name : &Token) {
+        if self.current().scope_depth > 0 {
+            self.add_local(&name.lexeme);
+        } else {
+            let index = self.name(&name.lexeme);
+            self.emit(Op::DefineGlobal(index), name.span);
+        }
+    }
+
+    fn resolve_local (&self, function : usize, name : &Symbol) -> Option<usize> {
+        self.functions[function].locals.iter().rposition(|l| l.name == *name)
+    }
+
+    fn resolve_upvalue (&mut self, function : usize, name : &Symbol) -> Option<usize> {
+        if function == 0 {
+            return None;
+        }
+        if let Some(slot) = self.resolve_local(function - 1, name) {
+            self.functions[function - 1].locals[slot].captured = true;
+            return Some(self.add_upvalue(function, UpvalueRef { is_local : true, index : slot }));
+        }
+        let index = self.resolve_upvalue(function - 1, name)?;
+        Some(self.add_upvalue(function, UpvalueRef { is_local : false, index }))
+    }
+
+    fn add_upvalue (&mut self, function : usize, upvalue : UpvalueRef) -> usize {
+        let upvalues = &mut self.functions[function].proto.upvalues;
+        match upvalues.iter().position(|u| *u == upvalue) {
+            Some(i) => i,
+            None => {
+                upvalues.push(upvalue);
+                upvalues.len() - 1
+            }
+        }
+    }
+
+    fn get_variable (&mut self, name : &Symbol, span : Span) {
+        let function = self.functions.len() - 1;
+        let op = if let Some(slot) = self.resolve_local(function, name) {
+            Op::GetLocal(slot)
+        } else if let Some(index) = self.resolve_upvalue(function, name) {
+            Op::GetUpvalue(index)
+        } else {
+            Op::GetGlobal(self.name(name))
+        };
+        self.emit(op, span);
+    }
+
+    fn set_variable (&mut self, name : &Symbol, span : Span) {
+        let function = self.functions.len() - 1;
+        let op = if let Some(slot) = self.resolve_local(function, name) {
+            Op::SetLocal(slot)
+        } else if let Some(index) = self.resolve_upvalue(function, name) {
+            Op::SetUpvalue(index)
+        } else {
+            Op::SetGlobal(self.name(name))
+        };
+        self.emit(op, span);
+    }
+
+    // * Pops the handlers of the try blocks above `count` and runs their
+    // * finally blocks inline, innermost first, before a jump out of them
+    fn leave_tries (&mut self, count : usize, span : Span) {
+        let tries = std::mem::take(&mut self.current().tries);
+        for i in (count..tries.len()).rev() {
+            self.emit(Op::PopHandler, span);
+            if let Some(finally) = &tries[i].finally {
+                // a finally block that jumps out itself only sees the tries around it
+                self.current().tries = tries.iter().take(i).map(|t| Try { finally : t.finally.clone() }).collect();
+                self.block(finally, span);
+            }
+        }
+        self.current().tries = tries;
+    }
+
+    // * Index of the loop `break`/`continue` targets; the resolver has
+    // * already rejected unknown labels
+    fn target_loop (&mut self, label : &Option<Token>) -> usize {
+        let loops = &self.current().loops;
+        match label {
+            None => loops.len() - 1,
+            Some(label) => loops.iter().rposition(|l| l.label.as_ref() == Some(&label.lexeme)).unwrap(),
+        }
+    }
+
+    // * Leaves everything opened inside loop `target`, ready to jump to its
+    // * end or continue point
+    fn leave_loop_body (&mut self, target : usize, span : Span) {
+        let (locals, tries) = {
+            let l = &self.current().loops[target];
+            (l.locals, l.tries)
+        };
+        self.leave_tries(tries, span);
+        self.discard_locals(locals, span);
+        let inner_iterators = self.current().loops[target + 1..].iter().filter(|l| l.iterates).count();
+        for _ in 0..inner_iterators {
+            self.emit(Op::PopIter, span);
+        }
+    }
+}
+
+impl ExprVisitor<()> for Compiler {
+    fn visit_binary(&mut self, binary : &expr::Binary) {
+        self.expression(&binary.left);
+        self.expression(&binary.right);
+        self.emit(Op::Binary(binary.operator.token_type), binary.operator.span);
+    }
+
+    fn visit_logical(&mut self, logical : &expr::Logical) {
+        self.expression(&logical.left);
+        let short_circuit = match logical.operator.token_type {
+            TokenType::Or => Op::JumpIfTrue(0),
+            _ => Op::JumpIfFalse(0),
+        };
+        let jump = self.emit(short_circuit, logical.operator.span);
+        self.emit(Op::Pop, logical.operator.span);
+        self.expression(&logical.right);
+        self.patch(jump);
+    }
+
+    fn visit_call(&mut self, call : &expr::Call) {
+        self.expression(&call.callee);
+        for argument in &call.arguments {
+            self.expression(argument);
+        }
+        self.emit(Op::Call(call.arguments.len()), call.paren.span);
+    }
+
+    fn visit_get(&mut self, get : &expr::Get) {
+        self.expression(&get.object);
+        let name = self.name(&get.name.lexeme);
+        self.emit(Op::GetProperty(name), get.name.span);
+    }
+
+    fn visit_set(&mut self, set : &expr::Set) {
+        self.expression(&set.object);
+        self.expression(&set.value);
+        let name = self.name(&set.name.lexeme);
+        self.emit(Op::SetProperty(name), set.name.span);
+    }
+
+    fn visit_superr(&mut self, superr : &expr::Superr) {
+        self.get_variable(&Symbol::intern("this"), superr.keyword.span);
+        self.get_variable(&Symbol::intern("super"), superr.keyword.span);
+        let name = self.name(&superr.method.lexeme);
+        self.emit(Op::GetSuper(name), superr.method.span);
+    }
+
+    fn visit_this(&mut self, this : &expr::This) {
+        self.get_variable(&Symbol::intern("this"), this.keyword.span);
+    }
+
+    fn visit_grouping(&mut self, grouping : &expr::Grouping) {
+        self.expression(&grouping.expression);
+    }
+
+    fn visit_literal(&mut self, literal : &expr::Literal) {
+        let op = match &literal.value {
+            LiteralType::Nil => Op::Nil,
+            LiteralType::Bool(true) => Op::True,
+            LiteralType::Bool(false) => Op::False,
+            value => Op::Constant(self.chunk().constant(value.clone())),
+        };
+        self.emit(op, literal.span);
+    }
+
+    fn visit_unary(&mut self, unary : &expr::Unary) {
+        self.expression(&unary.right);
+        let op = match unary.operator.token_type {
+            TokenType::Minus => Op::Negate,
+            _ => Op::Not,
+        };
+        self.emit(op, unary.operator.span);
+    }
+
+    fn visit_conditional(&mut self, conditional : &expr::Conditional) {
+        self.expression(&conditional.condition);
+        let span = conditional.span;
+        let to_else = self.emit(Op::JumpIfFalse(0), span);
+        self.emit(Op::Pop, span);
+        self.expression(&conditional.then_branch);
+        let to_end = self.emit(Op::Jump(0), span);
+        self.patch(to_else);
+        self.emit(Op::Pop, span);
+        self.expression(&conditional.else_branch);
+        self.patch(to_end);
+    }
+
+    fn visit_variable(&mut self, variable : &expr::Variable) {
+        self.get_variable(&variable.name.lexeme, variable.name.span);
+    }
+
+    fn visit_assigment(&mut self, assigment : &expr::Assigment) {
+        self.expression(&assigment.value);
+        self.set_variable(&assigment.name.lexeme, assigment.name.span);
+    }
+
+    fn visit_list(&mut self, list : &expr::List) {
+        for element in &list.elements {
+            self.expression(element);
+        }
+        self.emit(Op::List(list.elements.len()), list.bracket.span);
+    }
+
+    fn visit_index(&mut self, index : &expr::Index) {
+        self.expression(&index.object);
+        self.expression(&index.index);
+        self.emit(Op::Index, index.bracket.span);
+    }
+
+    fn visit_setindex(&mut self, setindex : &expr::SetIndex) {
+        self.expression(&setindex.object);
+        self.expression(&setindex.index);
+        self.expression(&setindex.value);
+        self.emit(Op::SetIndex, setindex.bracket.span);
+    }
+
+    fn visit_map(&mut self, map : &expr::Map) {
+        for (key, value) in map.keys.iter().zip(map.values.iter()) {
+            self.expression(key);
+            self.expression(value);
+        }
+        self.emit(Op::Map(map.keys.len()), map.brace.span);
+    }
+
+    fn visit_lambda(&mut self, lambda : &expr::Lambda) {
+        self.function(&lambda.declaration, FunctionKind::Function);
+    }
+}
+
+impl StmtVisitor<()> for Compiler {
+    fn visit_expression(&mut self, expression : &stmt::Expression) {
+        self.expression(&expression.expression);
+        self.emit(Op::Pop, expression.span);
+    }
+
+    fn visit_function(&mut self, function : &stmt::Function) {
+        // declared before the body is compiled, so the function can call itself
+        if self.current().scope_depth > 0 {
+            self.add_local(&function.name.lexeme);
+            self.function(function, FunctionKind::Function);
+        } else {
+            self.function(function, FunctionKind::Function);
+            self.define(&function.name);
+        }
+    }
+
+    fn visit_print(&mut self, print : &stmt::Print) {
+        self.expression(&print.expression);
+        self.emit(Op::Print, print.span);
+    }
+
+    fn visit_var(&mut self, var : &stmt::Var) {
+        match &var.initializer {
+            Some(initializer) => self.expression(initializer),
+            None => {
+                self.emit(Op::Nil, var.span);
+            }
+        }
+        self.define(&var.name);
+    }
+
+    fn visit_block(&mut self, block : &stmt::Block) {
+        self.block(&block.statements, block.span);
+    }
+
+    fn visit_iff(&mut self, iff : &stmt::Iff) {
+        self.expression(&iff.condition);
+        let to_else = self.emit(Op::JumpIfFalse(0), iff.span);
+        self.emit(Op::Pop, iff.span);
+        self.statement(&iff.then_branch);
+        let to_end = self.emit(Op::Jump(0), iff.span);
+        self.patch(to_else);
+        self.emit(Op::Pop, iff.span);
+        if let Some(else_branch) = &iff.else_branch {
+            self.statement(else_branch);
+        }
+        self.patch(to_end);
+    }
+
+    fn visit_whilee(&mut self, whilee : &stmt::Whilee) {
+        let span = whilee.span;
+        let start = self.here();
+        self.expression(&whilee.condition);
+        let to_exit = self.emit(Op::JumpIfFalse(0), span);
+        self.emit(Op::Pop, span);
+
+        let (locals, tries) = (self.current().locals.len(), self.current().tries.len());
+        self.current().loops.push(Loop {
+            label : whilee.label.as_ref().map(|l| l.lexeme.clone()),
+            locals,
+            tries,
+            iterates : false,
+            breaks : vec![],
+            continues : vec![],
+        });
+        self.statement(&whilee.body);
+        let finished = self.current().loops.pop().unwrap();
+
+        // `continue` still runs the increment of a desugared for loop
+        for jump in finished.continues {
+            self.patch(jump);
+        }
+        if let Some(increment) = &whilee.increment {
+            self.expression(increment);
+            self.emit(Op::Pop, span);
+        }
+        self.emit(Op::Jump(start), span);
+
+        self.patch(to_exit);
+        self.emit(Op::Pop, span);
+        for jump in finished.breaks {
+            self.patch(jump);
+        }
+    }
+
+    fn visit_forin(&mut self, forin : &stmt::ForIn) {
+        let span = forin.keyword.span;
+        self.expression(&forin.iterable);
+        self.emit(Op::IterStart, span);
+
+        let start = self.here();
+        let to_exit = self.emit(Op::IterNext(0), span);
+        let (locals, tries) = (self.current().locals.len(), self.current().tries.len());
+        self.current().loops.push(Loop {
+            label : forin.label.as_ref().map(|l| l.lexeme.clone()),
+            locals,
+            tries,
+            iterates : true,
+            breaks : vec![],
+            continues : vec![],
+        });
+        // the value IterNext pushed is the loop variable, fresh every iteration
+        self.begin_scope();
+        self.add_local(&forin.name.lexeme);
+        self.statement(&forin.body);
+        self.end_scope(span);
+        let finished = self.current().loops.pop().unwrap();
+
+        for jump in finished.continues {
+            self.patch(jump);
+        }
+        self.emit(Op::Jump(start), span);
+
+        self.patch(to_exit);
+        for jump in finished.breaks {
+            self.patch(jump);
+        }
+        self.emit(Op::PopIter, span);
+    }
+
+    fn visit_breakk(&mut self, breakk : &stmt::Breakk) {
+        let target = self.target_loop(&breakk.label);
+        self.leave_loop_body(target, breakk.span);
+        let jump = self.emit(Op::Jump(0), breakk.span);
+        self.current().loops[target].breaks.push(jump);
+    }
+
+    fn visit_continuee(&mut self, continuee : &stmt::Continuee) {
+        let target = self.target_loop(&continuee.label);
+        self.leave_loop_body(target, continuee.span);
+        let jump = self.emit(Op::Jump(0), continuee.span);
+        self.current().loops[target].continues.push(jump);
+    }
+
+    fn visit_returnn(&mut self, returnn : &stmt::Returnn) {
+        let span = returnn.span;
+        if let Some(value) = &returnn.value {
+            self.expression(value);
+        } else {
+            self.emit(Op::Nil, span);
+        }
+        // initializers always hand back the new instance
+        if self.current().proto.kind == FunctionKind::Initializer {
+            self.emit(Op::Pop, span);
+            self.emit(Op::GetLocal(0), span);
+        }
+        if !self.current().tries.is_empty() {
+            // the return value sits in a slot of its own while finally blocks run
+            self.begin_scope();
+            self.add_local(&Symbol::intern(""));
+            self.leave_tries(0, span);
+            self.current().locals.pop();
+            self.current().scope_depth -= 1;
+        }
+        self.emit(Op::Return, span);
+    }
+
+    fn visit_class(&mut self, class : &stmt::Class) {
+        let span = class.name.span;
+        let name = self.name(&class.name.lexeme);
+        self.emit(Op::Class(name), span);
+        self.define(&class.name);
+
+        if let Some(super_class) = &class.super_class {
+            self.expression(super_class);
+            self.begin_scope();
+            self.add_local(&Symbol::intern("super"));
+            self.get_variable(&class.name.lexeme, span);
+            self.emit(Op::Inherit, span);
+        }
+
+        self.get_variable(&class.name.lexeme, span);
+        for method in &class.methods {
+            let kind = if method.name.lexeme == "init" { FunctionKind::Initializer } else { FunctionKind::Method };
+            self.function(method, kind);
+            let name = self.name(&method.name.lexeme);
+            self.emit(Op::Method(name), method.name.span);
+        }
+        self.emit(Op::Pop, span);
+
+        if class.super_class.is_some() {
+            self.end_scope(span);
+        }
+    }
+
+    fn visit_throw(&mut self, throw : &stmt::Throw) {
+        self.expression(&throw.value);
+        self.emit(Op::Throw, throw.keyword.span);
+    }
+
+    fn visit_tryy(&mut self, tryy : &stmt::Tryy) {
+        let span = tryy.keyword.span;
+        let finally = tryy.finally_body.clone();
+
+        let handler = self.emit(Op::PushHandler { target : 0, rethrow : tryy.catch_body.is_none() }, span);
+        self.current().tries.push(Try { finally : finally.clone() });
+        self.block(&tryy.body, span);
+        self.current().tries.pop();
+        self.emit(Op::PopHandler, span);
+        if let Some(finally) = &finally {
+            self.block(finally, span);
+        }
+        let to_end = self.emit(Op::Jump(0), span);
+        self.patch(handler);
+
+        match (&tryy.catch_name, &tryy.catch_body) {
+            (Some(name), Some(body)) => {
+                // errors in the catch block still run the finally block
+                let rethrow = finally.as_ref().map(|_| self.emit(Op::PushHandler { target : 0, rethrow : true }, span));
+                self.begin_scope();
+                self.add_local(&name.lexeme);
+                if finally.is_some() {
+                    self.current().tries.push(Try { finally : finally.clone() });
+                }
+                for statement in body {
+                    self.statement(statement);
+                }
+                if let Some(finally) = &finally {
+                    self.current().tries.pop();
+                    self.emit(Op::PopHandler, span);
+                    self.end_scope(span);
+                    self.block(finally, span);
+                } else {
+                    self.end_scope(span);
+                }
+
+                if let (Some(rethrow), Some(finally)) = (rethrow, &finally) {
+                    let to_end = self.emit(Op::Jump(0), span);
+                    self.patch(rethrow);
+                    // the caught value is still in its slot
+                    self.emit(Op::CloseUpvalue, span);
+                    self.block(finally, span);
+                    self.emit(Op::Rethrow, span);
+                    self.patch(to_end);
+                }
+            }
+            _ => {
+                if let Some(finally) = &finally {
+                    self.block(finally, span);
+                }
+                self.emit(Op::Rethrow, span);
+            }
+        }
+        self.patch(to_end);
+    }
+}
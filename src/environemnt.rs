@@ -2,11 +2,14 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 use crate::scanner::LiteralType;
+use crate::symbol::Symbol;
 
-
+// ? Globals are looked up by name; every other scope holds its locals in
+// ? declaration order, at the slots the `Resolver` numbered them with
 #[derive(Debug, Clone)]
 pub struct Environemnt {
-    pub values : HashMap<String, LiteralType>,
+    pub values : HashMap<Symbol, LiteralType>,
+    pub slots : Vec<LiteralType>,
     pub previous : Option<Rc<RefCell<Environemnt>>>,
 }
 
@@ -15,28 +18,36 @@ impl Environemnt {
     pub fn new (parent : Option<Rc<RefCell<Environemnt>>> ) -> Environemnt {
         Environemnt {
             values : HashMap::new(),
+            slots : vec![],
             previous : parent
         }
     }
 
-    pub fn define (&mut self, name : String, value : LiteralType) {
+    // * Global definition
+    pub fn define (&mut self, name : Symbol, value : LiteralType) {
         self.values.insert(name, value);
     }
 
-    pub fn get_at (&mut self, distance : i32, name : String) -> Option<LiteralType> {
+    // * Local definition, in the next slot
+    pub fn push (&mut self, value : LiteralType) -> usize {
+        self.slots.push(value);
+        self.slots.len() - 1
+    }
+
+    pub fn get_at (&self, distance : usize, slot : usize) -> LiteralType {
         if distance == 0 {
-            return self.values.get(&name).cloned();
+            return self.slots[slot].clone();
         }
-        
-        self.previous.as_ref().unwrap().borrow_mut().get_at(distance - 1, name)
+
+        self.previous.as_ref().unwrap().borrow().get_at(distance - 1, slot)
     }
 
-    pub fn assign_at (&mut self, distance : i32, name : String, value : LiteralType) {
+    pub fn assign_at (&mut self, distance : usize, slot : usize, value : LiteralType) {
         if distance == 0 {
-            self.values.insert(name, value);
+            self.slots[slot] = value;
             return
         }
-        
-        self.previous.as_ref().unwrap().borrow_mut().assign_at(distance - 1, name, value)
+
+        self.previous.as_ref().unwrap().borrow_mut().assign_at(distance - 1, slot, value)
     }
-}
\ No newline at end of file
+}
@@ -1,51 +1,215 @@
-use crate::scanner::{Token, TokenType};
+use std::fmt::Display;
 
+use crate::scanner::{Span, Token, TokenType};
+
+#[derive(Debug)]
 pub struct ParseError {
-    pub line : i32,
+    pub span : Span,
+    // * " at end" / " at 'x'", rendered between "Error" and the message
+    pub location : String,
     pub message : String,
+    pub help : Option<String>,
 }
 
 #[derive(Debug)]
 pub struct RuntimeError {
     pub token : Token,
     pub message : String,
+    // * innermost call first; empty for errors raised outside any call
+    pub trace : Vec<TraceFrame>,
+}
+
+// ? One active call: the callee's name and where it was called from
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceFrame {
+    pub function : String,
+    pub call_site : Span,
 }
 
+#[derive(Debug)]
 pub struct ScannerError {
-    pub line : i32,
+    pub span : Span,
     pub message : String,
+    pub help : Option<String>,
 }
 
+#[derive(Debug)]
 pub struct ResolverError {
-    pub line : i32,
+    pub span : Span,
     pub message : String,
+    pub help : Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
 }
 
-pub enum LoxError {
-    ParseError (ParseError),
-    RuntimeError (RuntimeError),
-    ScannerError (ScannerError),
+// ? One code per stage of the pipeline the diagnostic was raised in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    Scanner,
+    Parser,
+    Resolver,
+    Runtime,
 }
 
-fn report (line : i32, loc : String, msg : &str) {
-    println!("[line {}] Error {}: {}", line, loc, msg);
+impl ErrorCode {
+    pub fn as_str (&self) -> &'static str {
+        match self {
+            ErrorCode::Scanner => "E0001",
+            ErrorCode::Parser => "E0002",
+            ErrorCode::Resolver => "E0003",
+            ErrorCode::Runtime => "E0004",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity : Severity,
+    pub code : ErrorCode,
+    pub file : Option<String>,
+    pub span : Span,
+    pub location : String,
+    pub message : String,
+    pub notes : Vec<String>,
+    pub help : Option<String>,
 }
 
-pub fn err (line : i32, msg : &str) -> bool {
-    report(line,  "".to_string(), msg);
-    // TODO: Rewrite the 
-    return true;
+impl Diagnostic {
+    pub fn error (code : ErrorCode, span : Span, message : &str) -> Diagnostic {
+        Diagnostic {
+            severity : Severity::Error,
+            code,
+            file : None,
+            span,
+            location : String::new(),
+            message : message.to_string(),
+            notes : vec![],
+            help : None,
+        }
+    }
+
+    pub fn with_note (mut self, note : &str) -> Diagnostic {
+        self.notes.push(note.to_string());
+        self
+    }
+
+    pub fn with_help (mut self, help : Option<String>) -> Diagnostic {
+        self.help = help;
+        self
+    }
+}
+
+// * Keeps the classic one-line text, byte for byte: "[line N] Error : message",
+// * or "[line N] Error  at 'x': message" with a location. `render` produces
+// * the long form with the source snippet
+impl Display for Diagnostic {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "Error",
+            Severity::Warning => "Warning",
+        };
+        write!(f, "[line {}] {} {}: {}", self.span.line, severity, self.location, self.message)
+    }
+}
+
+impl From<ScannerError> for Diagnostic {
+    fn from (e : ScannerError) -> Diagnostic {
+        Diagnostic::error(ErrorCode::Scanner, e.span, &e.message).with_help(e.help)
+    }
+}
+
+impl From<ParseError> for Diagnostic {
+    fn from (e : ParseError) -> Diagnostic {
+        let mut d = Diagnostic::error(ErrorCode::Parser, e.span, &e.message).with_help(e.help);
+        d.location = e.location;
+        d
+    }
+}
+
+impl From<ResolverError> for Diagnostic {
+    fn from (e : ResolverError) -> Diagnostic {
+        Diagnostic::error(ErrorCode::Resolver, e.span, &e.message).with_help(e.help)
+    }
+}
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from (e : &RuntimeError) -> Diagnostic {
+        let mut d = Diagnostic::error(ErrorCode::Runtime, e.token.span, &e.message);
+        for frame in &e.trace {
+            d = d.with_note(&format!("in {}() called from line {}", frame.function, frame.call_site.line));
+        }
+        d
+    }
+}
+
+// ? Collects diagnostics from every stage so embedders can inspect them
+// ? instead of scraping stdout
+#[derive(Debug, Default, Clone)]
+pub struct DiagnosticSink {
+    diagnostics : Vec<Diagnostic>,
+}
+
+impl DiagnosticSink {
+    pub fn new () -> DiagnosticSink {
+        DiagnosticSink { diagnostics : vec![] }
+    }
+
+    pub fn push (&mut self, diagnostic : impl Into<Diagnostic>) {
+        self.diagnostics.push(diagnostic.into());
+    }
+
+    pub fn iter (&self) -> std::slice::Iter<'_, Diagnostic> {
+        self.diagnostics.iter()
+    }
+
+    pub fn len (&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    pub fn is_empty (&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    pub fn has_errors (&self) -> bool {
+        self.diagnostics.iter().any(|d| d.severity == Severity::Error)
+    }
+
+    // * Stamps every collected diagnostic with the file it came from
+    pub fn set_file (&mut self, file : &str) {
+        for d in self.diagnostics.iter_mut() {
+            d.file = Some(file.to_string());
+        }
+    }
+
+    pub fn into_vec (self) -> Vec<Diagnostic> {
+        self.diagnostics
+    }
+}
+
+impl<'a> IntoIterator for &'a DiagnosticSink {
+    type Item = &'a Diagnostic;
+    type IntoIter = std::slice::Iter<'a, Diagnostic>;
+
+    fn into_iter (self) -> Self::IntoIter {
+        self.diagnostics.iter()
+    }
 }
 
 pub fn parse_error (token : &Token, msg : &str) -> ParseError{
-    if token.token_type == TokenType::EOF {
-        report(token.line, " at end".to_string(), msg);
+    let location = if token.token_type == TokenType::EOF {
+        " at end".to_string()
     } else {
-        report(token.line, format!(" at '{}'", token.lexeme), msg);
-    }
+        format!(" at '{}'", token.lexeme)
+    };
 
     ParseError {
-            line: token.line,
+            span: token.span,
+            location,
             message: msg.to_string(),
+            help: None,
         }
-}
\ No newline at end of file
+}
@@ -0,0 +1,243 @@
+use crate::scanner::{Token, LiteralType, Span};
+use crate::stmt::Function;
+use crate::parser::NodeId;
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Binary (Binary),
+    Logical (Logical),
+    Call (Call),
+    Get (Get),
+    Set (Set),
+    Superr (Superr),
+    This (This),
+    Grouping (Grouping),
+    Literal (Literal),
+    Unary (Unary),
+    Conditional (Conditional),
+    Variable (Variable),
+    Assigment (Assigment),
+    List (List),
+    Index (Index),
+    SetIndex (SetIndex),
+    Map (Map),
+    Lambda (Lambda),
+}
+#[derive(Debug, Clone)]
+pub struct Binary {
+    pub left : Box<Expr>,
+    pub operator : Token,
+    pub right : Box<Expr>,
+    pub span : Span,
+    pub id : NodeId
+}
+#[derive(Debug, Clone)]
+pub struct Logical {
+    pub left : Box<Expr>,
+    pub operator : Token,
+    pub right : Box<Expr>,
+    pub span : Span,
+    pub id : NodeId
+}
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub callee : Box<Expr>,
+    pub paren : Token,
+    pub arguments : Vec<Expr>,
+    pub span : Span,
+    pub id : NodeId
+}
+#[derive(Debug, Clone)]
+pub struct Get {
+    pub object : Box<Expr>,
+    pub name : Token,
+    pub span : Span,
+    pub id : NodeId
+}
+#[derive(Debug, Clone)]
+pub struct Set {
+    pub object : Box<Expr>,
+    pub name : Token,
+    pub value : Box<Expr>,
+    pub span : Span,
+    pub id : NodeId
+}
+#[derive(Debug, Clone)]
+pub struct Superr {
+    pub keyword : Token,
+    pub method : Token,
+    pub span : Span,
+    pub id : NodeId
+}
+#[derive(Debug, Clone)]
+pub struct This {
+    pub keyword : Token,
+    pub span : Span,
+    pub id : NodeId
+}
+#[derive(Debug, Clone)]
+pub struct Grouping {
+    pub expression : Box<Expr>,
+    pub span : Span,
+    pub id : NodeId
+}
+#[derive(Debug, Clone)]
+pub struct Literal {
+    pub value : LiteralType,
+    pub span : Span,
+    pub id : NodeId
+}
+#[derive(Debug, Clone)]
+pub struct Unary {
+    pub operator : Token,
+    pub right : Box<Expr>,
+    pub span : Span,
+    pub id : NodeId
+}
+#[derive(Debug, Clone)]
+pub struct Conditional {
+    pub condition : Box<Expr>,
+    pub then_branch : Box<Expr>,
+    pub else_branch : Box<Expr>,
+    pub span : Span,
+    pub id : NodeId
+}
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub name : Token,
+    pub span : Span,
+    pub id : NodeId
+}
+#[derive(Debug, Clone)]
+pub struct Assigment {
+    pub name : Token,
+    pub value : Box<Expr>,
+    pub span : Span,
+    pub id : NodeId
+}
+#[derive(Debug, Clone)]
+pub struct List {
+    pub bracket : Token,
+    pub elements : Vec<Expr>,
+    pub span : Span,
+    pub id : NodeId
+}
+#[derive(Debug, Clone)]
+pub struct Index {
+    pub object : Box<Expr>,
+    pub bracket : Token,
+    pub index : Box<Expr>,
+    pub span : Span,
+    pub id : NodeId
+}
+#[derive(Debug, Clone)]
+pub struct SetIndex {
+    pub object : Box<Expr>,
+    pub bracket : Token,
+    pub index : Box<Expr>,
+    pub value : Box<Expr>,
+    pub span : Span,
+    pub id : NodeId
+}
+#[derive(Debug, Clone)]
+pub struct Map {
+    pub brace : Token,
+    pub keys : Vec<Expr>,
+    pub values : Vec<Expr>,
+    pub span : Span,
+    pub id : NodeId
+}
+#[derive(Debug, Clone)]
+pub struct Lambda {
+    pub declaration : Function,
+    pub span : Span,
+    pub id : NodeId
+}
+pub trait Visitor<T> {
+    fn visit_binary(&mut self, binary : &Binary) -> T;
+    fn visit_logical(&mut self, logical : &Logical) -> T;
+    fn visit_call(&mut self, call : &Call) -> T;
+    fn visit_get(&mut self, get : &Get) -> T;
+    fn visit_set(&mut self, set : &Set) -> T;
+    fn visit_superr(&mut self, superr : &Superr) -> T;
+    fn visit_this(&mut self, this : &This) -> T;
+    fn visit_grouping(&mut self, grouping : &Grouping) -> T;
+    fn visit_literal(&mut self, literal : &Literal) -> T;
+    fn visit_unary(&mut self, unary : &Unary) -> T;
+    fn visit_conditional(&mut self, conditional : &Conditional) -> T;
+    fn visit_variable(&mut self, variable : &Variable) -> T;
+    fn visit_assigment(&mut self, assigment : &Assigment) -> T;
+    fn visit_list(&mut self, list : &List) -> T;
+    fn visit_index(&mut self, index : &Index) -> T;
+    fn visit_setindex(&mut self, setindex : &SetIndex) -> T;
+    fn visit_map(&mut self, map : &Map) -> T;
+    fn visit_lambda(&mut self, lambda : &Lambda) -> T;
+}
+impl Expr {
+    pub fn accept<T>(&self, visitor : &mut dyn Visitor<T>) -> T {
+        match self {
+            Expr::Binary (binary) => visitor.visit_binary(binary),
+            Expr::Logical (logical) => visitor.visit_logical(logical),
+            Expr::Call (call) => visitor.visit_call(call),
+            Expr::Get (get) => visitor.visit_get(get),
+            Expr::Set (set) => visitor.visit_set(set),
+            Expr::Superr (superr) => visitor.visit_superr(superr),
+            Expr::This (this) => visitor.visit_this(this),
+            Expr::Grouping (grouping) => visitor.visit_grouping(grouping),
+            Expr::Literal (literal) => visitor.visit_literal(literal),
+            Expr::Unary (unary) => visitor.visit_unary(unary),
+            Expr::Conditional (conditional) => visitor.visit_conditional(conditional),
+            Expr::Variable (variable) => visitor.visit_variable(variable),
+            Expr::Assigment (assigment) => visitor.visit_assigment(assigment),
+            Expr::List (list) => visitor.visit_list(list),
+            Expr::Index (index) => visitor.visit_index(index),
+            Expr::SetIndex (setindex) => visitor.visit_setindex(setindex),
+            Expr::Map (map) => visitor.visit_map(map),
+            Expr::Lambda (lambda) => visitor.visit_lambda(lambda),
+          }
+      }
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Binary (e) => e.span,
+            Expr::Logical (e) => e.span,
+            Expr::Call (e) => e.span,
+            Expr::Get (e) => e.span,
+            Expr::Set (e) => e.span,
+            Expr::Superr (e) => e.span,
+            Expr::This (e) => e.span,
+            Expr::Grouping (e) => e.span,
+            Expr::Literal (e) => e.span,
+            Expr::Unary (e) => e.span,
+            Expr::Conditional (e) => e.span,
+            Expr::Variable (e) => e.span,
+            Expr::Assigment (e) => e.span,
+            Expr::List (e) => e.span,
+            Expr::Index (e) => e.span,
+            Expr::SetIndex (e) => e.span,
+            Expr::Map (e) => e.span,
+            Expr::Lambda (e) => e.span,
+          }
+      }
+    pub fn id(&self) -> NodeId {
+        match self {
+            Expr::Binary (e) => e.id,
+            Expr::Logical (e) => e.id,
+            Expr::Call (e) => e.id,
+            Expr::Get (e) => e.id,
+            Expr::Set (e) => e.id,
+            Expr::Superr (e) => e.id,
+            Expr::This (e) => e.id,
+            Expr::Grouping (e) => e.id,
+            Expr::Literal (e) => e.id,
+            Expr::Unary (e) => e.id,
+            Expr::Conditional (e) => e.id,
+            Expr::Variable (e) => e.id,
+            Expr::Assigment (e) => e.id,
+            Expr::List (e) => e.id,
+            Expr::Index (e) => e.id,
+            Expr::SetIndex (e) => e.id,
+            Expr::Map (e) => e.id,
+            Expr::Lambda (e) => e.id,
+          }
+      }
+}
+
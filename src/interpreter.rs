@@ -2,68 +2,201 @@
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
 
-use crate::scanner::{Token, TokenType};
+use crate::scanner::{Span, Token, TokenType};
+use crate::parser::NodeId;
 use crate::{expr, scanner::LiteralType, stmt};
-use crate::expr::{Binary, Call, Conditional, Expr, Grouping, Literal, Unary, Variable};
+use crate::expr::{Binary, Conditional, Expr, Grouping, Literal, Unary};
 use crate::stmt::{Expression, Print, Stmt};
-use crate::error_handler::{err, RuntimeError};
+use crate::error_handler::{RuntimeError, TraceFrame};
 use crate::environemnt::Environemnt;
-use crate::loxcallable::{Callable, LoxCLass, LoxCallable, LoxFunction, NativeFunction};
+use crate::loxcallable::{Arity, BuiltinFn, Callable, LoxCLass, LoxCallable, LoxFunction, LoxInstance, NativeFunction};
+use crate::list;
+use crate::string;
+use crate::math::{self, Random};
+use crate::io;
+use crate::map::{self, LoxMap, MapKey};
+use crate::iter::LoxIter;
+use crate::symbol::Symbol;
 // TODO: Add runtime error handling
 
-// TODO: Implement the following:
-// * Anonymus functions
 
 pub struct Interpreter {
     pub environment : Rc<RefCell<Environemnt>>,
     pub globals : Rc<RefCell<Environemnt>>,
-    pub locals : HashMap<Expr, usize>,
-    loop_break : bool,
-    loop_continue : bool,
-    in_loop : bool,
+    // scope depth and slot of each resolved local, by expression node
+    pub locals : HashMap<NodeId, (usize, usize)>,
+    pub call_stack : Vec<TraceFrame>,
+    // class of the instances native runtime errors turn into when caught
+    error_class : Rc<LoxCLass>,
+    // state behind Math.random, reset by Math.seed
+    pub random : Random,
+    // capability switch for the file/stdin natives, off in a sandbox
+    pub allow_io : bool,
+    // what the `args()` native returns
+    pub script_args : Vec<String>,
+    // `(` of the native call being run, where `native_error` reports
+    pub(crate) native_call_site : Option<Token>,
+    // where `print`, REPL echoes and `input` prompts go, and where
+    // `readLine`/`input` read from; stdout and stdin unless injected
+    output : Box<dyn Write>,
+    input : Box<dyn BufRead>,
 }
 #[derive(Debug)]
 pub enum Exit {
     Return (LiteralType),
-    RuntimeError (RuntimeError)
+    RuntimeError (Box<RuntimeError>),
+    Throw (Box<Thrown>),
+    // * unwind to the innermost loop, or to the loop with the given label
+    Break (Option<Symbol>),
+    Continue (Option<Symbol>),
+}
+
+#[derive(Debug)]
+pub struct Thrown {
+    pub value : LiteralType,
+    // * the `throw` keyword and the calls active at that point, to report uncaught exceptions
+    pub keyword : Token,
+    pub trace : Vec<TraceFrame>,
+}
+
+impl Default for Interpreter {
+    fn default () -> Interpreter {
+        Interpreter::new()
+    }
 }
 
 impl Interpreter {
 
     pub fn new () -> Interpreter {
+        Interpreter::with_io(Box::new(std::io::stdout()), Box::new(BufReader::new(std::io::stdin())))
+    }
+
+    // * Interpreter whose program output and input go through the given streams
+    pub fn with_io (stdout : Box<dyn Write>, stdin : Box<dyn BufRead>) -> Interpreter {
         let global = Rc::new(RefCell::new(Environemnt::new(None)));
 
         let mut i = Interpreter {
             environment : Rc::clone(&global),
             globals : Rc::clone(&global),
-            loop_break : false,
-            loop_continue : false,
-            in_loop : false,
+            call_stack : vec![],
             locals : HashMap::new(),
+            error_class : Rc::new(LoxCLass {
+                name : "Error".to_string(),
+                methods : HashMap::new(),
+                super_class : None,
+            }),
+            random : Random::default(),
+            allow_io : true,
+            script_args : vec![],
+            native_call_site : None,
+            output : stdout,
+            input : stdin,
         };
         i.define_global_funcs();
         i
     }
 
-    pub fn resolve (&mut self, expr : &Expr, depth : usize){
-        self.locals.insert(expr.clone(), depth);
+    // * Sends everything `print` writes to `output` instead of stdout
+    pub fn set_output (&mut self, output : Box<dyn Write>) {
+        self.output = output;
+    }
+
+    pub fn set_input (&mut self, input : Box<dyn BufRead>) {
+        self.input = input;
+    }
+
+    pub fn output (&mut self) -> &mut dyn Write {
+        &mut *self.output
+    }
+
+    // * One line of input without its line ending, None once input is exhausted
+    pub fn read_line (&mut self) -> std::io::Result<Option<String>> {
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let trimmed = line.trim_end_matches(['\n', '\r']).len();
+        line.truncate(trimmed);
+        Ok(Some(line))
+    }
+
+    pub fn resolve (&mut self, id : NodeId, depth : usize, slot : usize){
+        self.locals.insert(id, (depth, slot));
+    }
+
+    // * Binds a declaration in the current scope: by name at the top level,
+    // * otherwise in the next slot, which is the one the resolver numbered
+    fn declare (&mut self, name : &Symbol, value : LiteralType) -> Option<usize> {
+        if Rc::ptr_eq(&self.environment, &self.globals) {
+            self.globals.borrow_mut().define(name.clone(), value);
+            return None;
+        }
+        Some(self.environment.borrow_mut().push(value))
     }
 
     fn define_global_funcs (&mut self) {
         // clock
-        let clock_func = Callable::NativeFunction(NativeFunction {
-            name : "clock".to_string(),
-            arity : 0,
-            function : |_interpreter, _args| {
-                let time = std::time::SystemTime::now();
-                let since_the_epoch = time.duration_since(std::time::UNIX_EPOCH).unwrap();
-                LiteralType::Number(since_the_epoch.as_secs_f64())
+        self.define_native("clock", Arity::Exact(0), |_interpreter, _args| {
+            let time = std::time::SystemTime::now();
+            let since_the_epoch = time.duration_since(std::time::UNIX_EPOCH).unwrap();
+            Ok(LiteralType::Number(since_the_epoch.as_secs_f64()))
+        });
+
+        // range(end) or range(start, end), iterated lazily by for-in
+        self.define_builtin("range", Arity::Range(1, 2), |_interpreter, args| {
+            let (start, end) = match args {
+                [end] => (&LiteralType::Number(0.0), end),
+                [start, end] => (start, end),
+                _ => unreachable!(),
+            };
+            match (start, end) {
+                (LiteralType::Number(start), LiteralType::Number(end)) => Ok(LiteralType::Range(*start, *end)),
+                _ => Err("Range bounds must be numbers".to_string()),
             }
         });
-        self.globals.borrow_mut().define("clock".to_string(), LiteralType::Callable(clock_func));
 
-        // TODO: add file handling | buffer handling
+        // string conversions
+        self.define_builtin("str", Arity::Exact(1), string::str);
+        self.define_builtin("num", Arity::Exact(1), string::num);
+        self.define_builtin("chr", Arity::Exact(1), string::chr);
+        self.define_builtin("ord", Arity::Exact(1), string::ord);
+
+        let math = math::namespace();
+        self.globals.borrow_mut().define(Symbol::intern("Math"), LiteralType::Callable(Callable::LoxInstance(math)));
+
+        // files, stdin and command-line arguments
+        for (name, arity, function) in io::NATIVES {
+            self.define_builtin(name, arity, function);
+        }
+    }
+
+    // * Registers a global function implemented in Rust. The closure may capture
+    // * state; `native_error` turns a message into an error at the call site
+    pub fn define_native (&mut self, name : &str, arity : Arity, function : impl Fn (&mut Interpreter, &[LiteralType]) -> Result<LiteralType, Exit> + 'static) {
+        let native = Callable::NativeFunction(NativeFunction::new(name, arity, function));
+        self.globals.borrow_mut().define(Symbol::intern(name), LiteralType::Callable(native));
+    }
+
+    fn define_builtin (&mut self, name : &str, arity : Arity, function : BuiltinFn) {
+        let native = Callable::NativeFunction(NativeFunction::builtin(name, arity, function));
+        self.globals.borrow_mut().define(Symbol::intern(name), LiteralType::Callable(native));
+    }
+
+    // * Runtime error reported at the call to the native currently running
+    pub fn native_error (&self, message : impl Into<String>) -> Exit {
+        let token = self.native_call_site.clone().unwrap_or_else(|| Token {
+            token_type : TokenType::EOF,
+            lexeme : Symbol::intern(""),
+            literal : LiteralType::Nil,
+            span : Span::default(),
+        });
+        Exit::RuntimeError(Box::new(RuntimeError {
+            token,
+            message : message.into(),
+            trace : vec![]
+        }))
     }
 
     pub fn evaluate (&mut self, expr : &Expr) -> Result<LiteralType, Exit> {
@@ -74,51 +207,129 @@ impl Interpreter {
         stmt.accept(self)
     }
 
-    pub fn interpret (&mut self, stmts : Vec<Stmt>, repl : bool) -> Result<(), Exit> {
+    // * Returns the value of the trailing expression statement (if any), which the REPL echoes
+    pub fn interpret (&mut self, stmts : Vec<Stmt>, repl : bool) -> Result<Option<LiteralType>, Exit> {
+        let mut last = None;
         for stmt in stmts {
-        
-            if repl {
-                match stmt {
-                    Stmt::Expression(e) => {
-                        let val = self.evaluate(&e.expression)?;
+            match stmt {
+                Stmt::Expression(e) => {
+                    let val = self.evaluate(&e.expression)?;
+                    if repl {
                         self.print_val(&val);
-                        continue;
-                    },
-                    _ => {}
+                    }
+                    last = Some(val);
+                },
+                _ => {
+                    self.execute(&stmt)?;
+                    last = None;
                 }
             }
-            self.execute(&stmt)?;
         }
-        Ok(())
+        Ok(last)
     }
 
     // Helpers:
 
+    // * Runs `call` inside a new frame of the call stack. The innermost frame an
+    // * error unwinds through records the stack as the error's trace.
+    fn call_in_frame (&mut self, function : String, call_site : &Token, call : impl FnOnce(&mut Interpreter) -> Result<LiteralType, Exit>) -> Result<LiteralType, Exit> {
+        if self.call_stack.len() >= FRAMES_MAX {
+            return Err(Exit::RuntimeError(Box::new(RuntimeError {
+                token : call_site.clone(),
+                message : "Stack overflow".to_string(),
+                trace : vec![]
+            })));
+        }
+        self.call_stack.push(TraceFrame {
+            function,
+            call_site : call_site.span,
+        });
+        let mut result = call(self);
+        let trace = match &mut result {
+            Err(Exit::RuntimeError(e)) => Some(&mut e.trace),
+            Err(Exit::Throw(thrown)) => Some(&mut thrown.trace),
+            _ => None,
+        };
+        if let Some(trace) = trace.filter(|trace| trace.is_empty()) {
+            *trace = self.call_stack.iter().rev().cloned().collect();
+        }
+        self.call_stack.pop();
+        result
+    }
 
-    fn is_equal (&mut self, a : &LiteralType, b : &LiteralType) -> bool {
-        match (a, b) {
-            (LiteralType::Nil, LiteralType::Nil) => true,
-            (LiteralType::String(s1), LiteralType::String(s2)) => s1 == s2,
-            (LiteralType::Number(n1), LiteralType::Number(n2)) => n1 == n2,
-            (LiteralType::Bool(b1), LiteralType::Bool(b2)) => b1 == b2,
-            _ => false,
+    // * Calls the method `name` on `instance`, None if the class has no such method
+    pub fn call_method (&mut self, instance : &Rc<RefCell<LoxInstance>>, name : &str, args : &[LiteralType], call_site : &Token) -> Result<Option<LiteralType>, Exit> {
+        let method = match instance.borrow().find_method(name) {
+            Some(m) => m.bind(Rc::clone(instance)),
+            None => return Ok(None),
+        };
+        if !method.arity().accepts(args.len()) {
+            return Err(Exit::RuntimeError(Box::new(RuntimeError {
+                token : call_site.clone(),
+                message : format!("Expected '{}' to take {} arguments but it takes {}", name, args.len(), method.arity()),
+                trace : vec![]
+            })));
         }
+        self.call_in_frame(name.to_string(), call_site, |interpreter| method.call(interpreter, args)).map(Some)
     }
 
-    fn print_val (&self, value : &LiteralType) {
-        match value {
-            LiteralType::String(s) => println!("{}", s),
-            LiteralType::Number(n) => println!("{}", n),
-            LiteralType::Bool(b) => println!("{}", b),
-            LiteralType::Nil => println!("nil"),
-            LiteralType::Callable(c) => {
-                // TODO: print for lox callables
-                println!("{}", c);
+    // * `left == right` decided by an `equals(other)` method, asking the left
+    // * operand first; None when neither is an instance with one
+    fn user_equals (&mut self, left : &LiteralType, right : &LiteralType, operator : &Token) -> Result<Option<bool>, Exit> {
+        for (object, other) in [(left, right), (right, left)] {
+            if let LiteralType::Callable(Callable::LoxInstance(instance)) = object {
+                if let Some(result) = self.call_method(instance, "equals", std::slice::from_ref(other), operator)? {
+                    return Ok(Some(is_truthy(&result)));
+                }
             }
         }
+        Ok(None)
     }
 
-    pub fn execute_block (&mut self, statements : &Vec<Stmt>, environment : Environemnt, is_loop : bool) -> Result<(), Exit> {
+    // * Key in `map` of an instance with a `hash()` method: the stored key it
+    // * `equals`, or a new one. None for any other value
+    fn hashed_key (&mut self, map : &Rc<RefCell<LoxMap>>, value : &LiteralType, site : &Token) -> Result<Option<MapKey>, Exit> {
+        let LiteralType::Callable(object @ Callable::LoxInstance(instance)) = value else {
+            return Ok(None);
+        };
+        let message = match self.call_method(instance, "hash", &[], site)? {
+            Some(hash) => match MapKey::hashed(object, &hash) {
+                Ok(key) => {
+                    // `equals` may touch the map, so it isn't borrowed meanwhile
+                    let colliding = map.borrow().colliding(&key);
+                    for stored in colliding {
+                        if stored == key || self.user_equals(value, &stored.to_value(), site)? == Some(true) {
+                            return Ok(Some(stored));
+                        }
+                    }
+                    return Ok(Some(key));
+                }
+                Err(message) => message,
+            },
+            None if instance.borrow().find_method("equals").is_some() => UNHASHABLE.to_string(),
+            None => return Ok(None),
+        };
+        Err(Exit::RuntimeError(Box::new(RuntimeError {
+            token : site.clone(),
+            message,
+            trace : vec![]
+        })))
+    }
+
+    // * Instance of `Error` carrying the message and line of a native runtime error
+    pub(crate) fn error_value (&self, error : &RuntimeError) -> LiteralType {
+        let instance = LoxInstance::new(Rc::clone(&self.error_class));
+        instance.borrow_mut().define("message", LiteralType::String(error.message.as_str().into()));
+        instance.borrow_mut().define("line", LiteralType::Number(error.token.span.line as f64));
+        LiteralType::Callable(Callable::LoxInstance(instance))
+    }
+
+
+    pub(crate) fn print_val (&mut self, value : &LiteralType) {
+        writeln!(self.output, "{}", value).expect("Cannot write program output");
+    }
+
+    pub fn execute_block (&mut self, statements : &Vec<Stmt>, environment : Environemnt) -> Result<(), Exit> {
         let previous = Rc::clone(&self.environment);
 
         self.environment = Rc::new(RefCell::new(environment));
@@ -127,9 +338,6 @@ impl Interpreter {
         // dbg!(&self.environment);
 
         for stmt in statements {
-            if is_loop && (self.loop_break || self.loop_continue) {
-                break;
-            }
             let res = self.execute(stmt);
             match &res {
                 Ok (_) => {},
@@ -154,214 +362,239 @@ impl Interpreter {
         Ok(())
     }
 
-    fn look_up_variable (&mut self, name : Token, expr : &Expr) -> Result<LiteralType, Exit> {
-        let distance = self.locals.get(expr);
-
-        
-        match distance {
-            Some (d) => {
-                Ok(self.environment.borrow_mut().get_at(*d as i32, name.lexeme).unwrap())
+    fn look_up_variable (&mut self, name : &Token, id : NodeId) -> Result<LiteralType, Exit> {
+        match self.locals.get(&id) {
+            Some (&(depth, slot)) => {
+                Ok(self.environment.borrow().get_at(depth, slot))
             },
             None => {
                 //get at global scope
-                match self.globals.borrow_mut().get_at(0, name.lexeme.clone()) {
+                match self.globals.borrow().values.get(&name.lexeme) {
                     Some (val) => {
-                        return Ok(val.clone());
+                        Ok(val.clone())
                     },
                     None => {
-                        return Err(Exit::RuntimeError(RuntimeError {
+                        Err(Exit::RuntimeError(Box::new(RuntimeError {
                             token : name.clone(),
-                            message : format!("Undefined variable '{}'", name.lexeme.clone())
-                        }));
+                            message : format!("Undefined variable '{}'", name.lexeme),
+                            trace : vec![]
+                        })))
                     }
                 }
             }
         }
 
     }
-    fn report_run_time_error (&mut self) {
-        todo!()
-    }
 }
 
-fn is_truthy (literal : &LiteralType) -> bool {
+pub(crate) fn is_truthy (literal : &LiteralType) -> bool {
     match literal {
         LiteralType::Nil => false,
         LiteralType::String(s) => !s.is_empty(),
         LiteralType::Number(n) => *n != 0.0,
         LiteralType::Bool(b) => *b,
+        LiteralType::List(l) => !l.borrow().is_empty(),
+        LiteralType::Map(m) => !m.borrow().is_empty(),
+        LiteralType::Range(start, end) => start < end,
         _ => {
             false
         }
     }
 }
 
-impl expr::Visitor<Result<LiteralType, Exit>> for Interpreter {
-
-    fn visit_binary(&mut self, binary : &Binary) -> Result<LiteralType, Exit> {
-        let left = self.evaluate(&binary.left)?;
-        let right = self.evaluate(&binary.right)?;
+// ? Value semantics shared by both backends (this tree-walker and `vm`), so
+// ? they agree on every result and error message
 
-        let operator = &binary.operator.token_type;
-
-        match operator {
-            TokenType::Minus => {
-                match (left, right) {
-                    (LiteralType::Number(l), LiteralType::Number(r)) => Ok(LiteralType::Number(l - r)),
-                    // TODO: Report error for not a number
-                    _ => {
-                        Err(Exit::RuntimeError(RuntimeError {
-                            token : binary.operator.clone(),
-                            message : "Operands must be numbers".to_string()
-                        }))
-                    }
-                }
-            }
-            TokenType::Plus => {
-                match (left, right) {
-                    (LiteralType::Number(l), LiteralType::Number(r)) => Ok(LiteralType::Number(l + r)),
-                    (LiteralType::String(l), LiteralType::String(r)) => Ok(LiteralType::String(format!("{}{}", l, r))),
-                    
-                    // TODO: Casting to string
-                    // * DONE
-                    (LiteralType::Number(n), LiteralType::String(s)) => {
-                        Ok(LiteralType::String(format!("{}{}", n, s)))
-                    }
-                    (LiteralType::String(s), LiteralType::Number(n)) => {
-                        Ok(LiteralType::String(format!("{}{}", s, n)))
-                    }
-                    // TODO: Return runtime error for invalid types
-                    _ => {
-                        Err(Exit::RuntimeError(RuntimeError {
-                            token : binary.operator.clone(),
-                            message : "Operands must be two numbers or two strings".to_string()
-                        }))
-                    }
-                }
-            }
+// * instances that define `equals` without `hash` could be equal yet land
+// * in different map entries, so they cannot be keys at all
+pub(crate) const UNHASHABLE : &str = "Instances with an 'equals()' method need a 'hash()' method to be map keys";
 
-            TokenType::Star => {
-                match (left, right) {
-                    (LiteralType::Number(l), LiteralType::Number(r)) => Ok(LiteralType::Number(l * r)),
-                    // TODO: Report error for not a number
-                    _ => {
-                        Err(Exit::RuntimeError(RuntimeError {
-                            token : binary.operator.clone(),
-                            message : "Operands must be numbers".to_string()
-                        }))
-                    }
-                }
-            } 
-
-            TokenType::Slash => {
-                match (left, right) {
-                    (LiteralType::Number(l), LiteralType::Number(r)) => {
-                        // TODO: Return runtime error for division by zero
-
-                        if r == 0.0 {
-                            return Err(Exit::RuntimeError(RuntimeError {
-                                token : binary.operator.clone(),
-                                message : "Division by zero".to_string()
-                            }));
-                        }
+// * Calls deep enough for any sane recursion, shallow enough to fail with a
+// * "Stack overflow" error before memory does; see `lox::STACK_SIZE`
+pub const FRAMES_MAX : usize = 10_000;
 
-                        Ok(LiteralType::Number(l / r))
-                    },
-                    // TODO: Report error for not a number
-                    _ => {
-                        Err(Exit::RuntimeError(RuntimeError {
-                            token : binary.operator.clone(),
-                            message : "Operands must be numbers".to_string()
-                        }))
-                    }
-                }
-            }
+pub(crate) fn is_equal (a : &LiteralType, b : &LiteralType) -> bool {
+    equal_within(a, b, &mut vec![])
+}
 
-            // modulo operator
-            TokenType::Percentage => {
-                match (left, right) {
-                    (LiteralType::Number(l), LiteralType::Number(r)) => {
-                        // try casting into integer
-                        Ok(LiteralType::Number(l % r))
-                    },
-                    _ => {
-                        // TODO: Report error for not a number
-                        Err(Exit::RuntimeError(RuntimeError {
-                            token : binary.operator.clone(),
-                            message : "Operands must be numbers".to_string()
-                        }))
-                    }
-                }
-            }
-            // comparison operators
-            TokenType::Greater => {
-                match (left, right) {
-                    (LiteralType::Number(l), LiteralType::Number(r)) => Ok(LiteralType::Bool(l > r)),
-                    // TODO: Report error for not a number
-                    _ => {
-                        Err(Exit::RuntimeError(RuntimeError {
-                            token : binary.operator.clone(),
-                            message : "Operands must be numbers".to_string()
-                        }))
-                    }
-                }
-            }
-            TokenType::GreaterEqual => {
-                match (left, right) {
-                    (LiteralType::Number(l), LiteralType::Number(r)) => Ok(LiteralType::Bool(l >= r)),
-                    // TODO: Report error for not a number
-                    _ => {
-                        Err(Exit::RuntimeError(RuntimeError {
-                            token : binary.operator.clone(),
-                            message : "Operands must be numbers".to_string()
-                        }))
-                    }
-                }
-            }
-            TokenType::Less => {
-                match (left, right) {
-                    (LiteralType::Number(l), LiteralType::Number(r)) => Ok(LiteralType::Bool(l < r)),
-                    // TODO: Report error for not a number
-                    _ => {
-                        Err(Exit::RuntimeError(RuntimeError {
-                            token : binary.operator.clone(),
-                            message : "Operands must be numbers".to_string()
-                        }))
-                    }
-                }
+// * `comparing` holds the pairs of lists or maps being compared further out.
+// * Meeting one again means both contain themselves there, which is no
+// * difference, so the pair counts as equal at that point
+fn equal_within (a : &LiteralType, b : &LiteralType, comparing : &mut Vec<(usize, usize)>) -> bool {
+    match (a, b) {
+        (LiteralType::Nil, LiteralType::Nil) => true,
+        (LiteralType::String(s1), LiteralType::String(s2)) => s1 == s2,
+        (LiteralType::Number(n1), LiteralType::Number(n2)) => n1 == n2,
+        (LiteralType::Bool(b1), LiteralType::Bool(b2)) => b1 == b2,
+        (LiteralType::List(l1), LiteralType::List(l2)) => {
+            let pair = (Rc::as_ptr(l1) as usize, Rc::as_ptr(l2) as usize);
+            if Rc::ptr_eq(l1, l2) || comparing.contains(&pair) {
+                return true;
             }
-            TokenType::LessEqual => {
-                match (left, right) {
-                    (LiteralType::Number(l), LiteralType::Number(r)) => Ok(LiteralType::Bool(l <= r)),
-                    // TODO: Report error for not a number
-                    _ => {
-                        Err(Exit::RuntimeError(RuntimeError {
-                            token : binary.operator.clone(),
-                            message : "Operands must be numbers".to_string()
-                        }))
-                    }
-                }
+            comparing.push(pair);
+            let (l1, l2) = (l1.borrow(), l2.borrow());
+            let equal = l1.len() == l2.len() && l1.iter().zip(l2.iter()).all(|(a, b)| equal_within(a, b, comparing));
+            comparing.pop();
+            equal
+        }
+        (LiteralType::Range(s1, e1), LiteralType::Range(s2, e2)) => s1 == s2 && e1 == e2,
+        (LiteralType::Callable(c1), LiteralType::Callable(c2)) => c1.same(c2),
+        (LiteralType::Map(m1), LiteralType::Map(m2)) => {
+            let pair = (Rc::as_ptr(m1) as usize, Rc::as_ptr(m2) as usize);
+            if Rc::ptr_eq(m1, m2) || comparing.contains(&pair) {
+                return true;
             }
-            TokenType::EqualEqual => {
-                Ok(LiteralType::Bool(self.is_equal(&left, &right)))
+            comparing.push(pair);
+            let (m1, m2) = (m1.borrow(), m2.borrow());
+            let equal = m1.len() == m2.len() && m1.iter().all(|(k, v)| m2.get(k).is_some_and(|other| equal_within(v, other, comparing)));
+            comparing.pop();
+            equal
+        }
+        _ => false,
+    }
+}
+
+pub(crate) fn binary_op (operator : &TokenType, left : LiteralType, right : LiteralType) -> Result<LiteralType, String> {
+    let numbers = || "Operands must be numbers".to_string();
+    match operator {
+        TokenType::Plus => match (left, right) {
+            (LiteralType::Number(l), LiteralType::Number(r)) => Ok(LiteralType::Number(l + r)),
+            (LiteralType::String(l), LiteralType::String(r)) => Ok(LiteralType::String(format!("{}{}", l, r).into())),
+            // a number next to a string is concatenated as text
+            (LiteralType::Number(n), LiteralType::String(s)) => Ok(LiteralType::String(format!("{}{}", n, s).into())),
+            (LiteralType::String(s), LiteralType::Number(n)) => Ok(LiteralType::String(format!("{}{}", s, n).into())),
+            _ => Err("Operands must be two numbers or two strings".to_string()),
+        },
+        TokenType::EqualEqual => Ok(LiteralType::Bool(is_equal(&left, &right))),
+        TokenType::BangEqual => Ok(LiteralType::Bool(!is_equal(&left, &right))),
+        TokenType::Comma => Ok(right),
+        _ => {
+            let (LiteralType::Number(l), LiteralType::Number(r)) = (left, right) else {
+                return Err(numbers());
+            };
+            match operator {
+                TokenType::Minus => Ok(LiteralType::Number(l - r)),
+                TokenType::Star => Ok(LiteralType::Number(l * r)),
+                TokenType::Slash if r == 0.0 => Err("Division by zero".to_string()),
+                TokenType::Slash => Ok(LiteralType::Number(l / r)),
+                TokenType::Percentage => Ok(LiteralType::Number(l % r)),
+                TokenType::Greater => Ok(LiteralType::Bool(l > r)),
+                TokenType::GreaterEqual => Ok(LiteralType::Bool(l >= r)),
+                TokenType::Less => Ok(LiteralType::Bool(l < r)),
+                TokenType::LessEqual => Ok(LiteralType::Bool(l <= r)),
+                _ => unreachable!("{:?} is not a binary operator", operator),
             }
-            TokenType::BangEqual => {
-                Ok(LiteralType::Bool(!self.is_equal(&left, &right)))
+        }
+    }
+}
+
+pub(crate) fn unary_op (operator : &TokenType, right : LiteralType) -> Result<LiteralType, String> {
+    match operator {
+        TokenType::Minus => match right {
+            LiteralType::Number(n) => Ok(LiteralType::Number(-n)),
+            _ => Err("Operand must be a number".to_string()),
+        },
+        TokenType::Bang => Ok(LiteralType::Bool(!is_truthy(&right))),
+        _ => unreachable!("{:?} is not a unary operator", operator),
+    }
+}
+
+// * Native method `name` of a list, string or map; None for any other value
+pub(crate) fn value_method (object : &LiteralType, name : &str) -> Option<Result<LiteralType, String>> {
+    let (method, kind) = match object {
+        LiteralType::List(l) => (list::method(l, name), "list"),
+        LiteralType::String(s) => (string::method(s, name), "string"),
+        LiteralType::Map(m) => (map::method(m, name), "map"),
+        _ => return None,
+    };
+    Some(match method {
+        Some(method) => Ok(LiteralType::Callable(Callable::NativeMethod(method))),
+        None => Err(format!("Undefined {} method '{}'", kind, name)),
+    })
+}
+
+// * `hashed` is the map key of an instance key with a `hash()` method
+pub(crate) fn get_index (object : &LiteralType, key : &LiteralType, hashed : Option<MapKey>) -> Result<LiteralType, String> {
+    match object {
+        LiteralType::List(l) => {
+            let l = l.borrow();
+            Ok(l[list::element_index(key, l.len())?].clone())
+        }
+        LiteralType::Map(m) => {
+            let k = match hashed {
+                Some(k) => k,
+                None => MapKey::from_value(key)?,
+            };
+            match m.borrow().get(&k) {
+                Some(value) => Ok(value.clone()),
+                None => Err(format!("Undefined key {}", key.repr())),
             }
-            TokenType::Comma => {
-                Ok(right)
+        }
+        _ => Err("Only lists and maps can be indexed".to_string()),
+    }
+}
+
+pub(crate) fn set_index (object : &LiteralType, key : &LiteralType, hashed : Option<MapKey>, value : LiteralType) -> Result<(), String> {
+    match object {
+        LiteralType::List(l) => {
+            let mut l = l.borrow_mut();
+            let i = list::element_index(key, l.len())?;
+            l[i] = value;
+            Ok(())
+        }
+        LiteralType::Map(m) => {
+            let k = match hashed {
+                Some(k) => k,
+                None => MapKey::from_value(key)?,
+            };
+            m.borrow_mut().insert(k, value);
+            Ok(())
+        }
+        _ => Err("Only lists and maps can be indexed".to_string()),
+    }
+}
+
+// * Handles how one run of a loop body ended: Ok(true) to keep looping,
+// * Ok(false) to leave this loop, Err to keep unwinding past it
+fn continues (result : Result<(), Exit>, label : &Option<Token>) -> Result<bool, Exit> {
+    let targets = |target : &Option<Symbol>| match target {
+        None => true,
+        Some(target) => label.as_ref().is_some_and(|l| &l.lexeme == target),
+    };
+    match result {
+        Ok(()) => Ok(true),
+        Err(Exit::Continue(target)) if targets(&target) => Ok(true),
+        Err(Exit::Break(target)) if targets(&target) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+impl expr::Visitor<Result<LiteralType, Exit>> for Interpreter {
+
+    fn visit_binary(&mut self, binary : &Binary) -> Result<LiteralType, Exit> {
+        let left = self.evaluate(&binary.left)?;
+        let right = self.evaluate(&binary.right)?;
+
+        if let TokenType::EqualEqual | TokenType::BangEqual = binary.operator.token_type {
+            if let Some(equal) = self.user_equals(&left, &right, &binary.operator)? {
+                return Ok(LiteralType::Bool(equal == (binary.operator.token_type == TokenType::EqualEqual)));
             }
-            _ => {panic!("{:?}", binary)}
         }
+
+        binary_op(&binary.operator.token_type, left, right).map_err(|message| Exit::RuntimeError(Box::new(RuntimeError {
+            token : binary.operator.clone(),
+            message,
+            trace : vec![]
+        })))
     }
 
     fn visit_conditional(&mut self, conditional : &Conditional) -> Result<LiteralType, Exit> {
         let conditiona = self.evaluate(&conditional.condition)?;
 
         if is_truthy(&conditiona) {
-            return self.evaluate(&conditional.then_branch);
+            self.evaluate(&conditional.then_branch)
         } else {
-            return self.evaluate(&conditional.else_branch);
+            self.evaluate(&conditional.else_branch)
         }
     }
 
@@ -375,42 +608,27 @@ impl expr::Visitor<Result<LiteralType, Exit>> for Interpreter {
 
     fn visit_unary(&mut self, unary : &Unary) -> Result<LiteralType, Exit> {
         let right : LiteralType = self.evaluate(&unary.right)?;
-        let operator = &unary.operator;
-
-        match operator.token_type {
-            TokenType::Minus => {
-                match right {
-                    LiteralType::Number(n) => Ok(LiteralType::Number(-n)),
-                    // TODO: Report error for not a number
-                    _ => {
-                        Err(Exit::RuntimeError(RuntimeError {
-                            token : operator.clone(),
-                            message : "Operand must be a number".to_string()
-                        }))
-                    }
-                }
-            },
-            TokenType::Bang => {
-                Ok(LiteralType::Bool(!is_truthy(&right)))
-            },
-            _ => {unreachable!()}
-        }
+
+        unary_op(&unary.operator.token_type, right).map_err(|message| Exit::RuntimeError(Box::new(RuntimeError {
+            token : unary.operator.clone(),
+            message,
+            trace : vec![]
+        })))
     }
 
     fn visit_variable(&mut self, variable : &expr::Variable) -> Result<LiteralType, Exit> {
-        return self.look_up_variable(variable.name.clone(), &Expr::Variable(variable.clone()))
+        self.look_up_variable(&variable.name, variable.id)
     }
     fn visit_assigment(&mut self, assigment : &expr::Assigment) -> Result<LiteralType, Exit> {
         let value = self.evaluate(&assigment.value)?;
         let name = &assigment.name.lexeme;
 
-        let distance = self.locals.get(&Expr::Assigment(assigment.clone()));
-        match distance {
-            Some (d) => {
-                self.environment.borrow_mut().assign_at(*d as i32, name.clone(), value.clone())
+        match self.locals.get(&assigment.id) {
+            Some (&(depth, slot)) => {
+                self.environment.borrow_mut().assign_at(depth, slot, value.clone())
             },
             None => {
-                self.globals.borrow_mut().assign_at(0, name.clone(), value.clone())
+                self.globals.borrow_mut().define(name.clone(), value.clone())
             }
         };
 
@@ -433,7 +651,7 @@ impl expr::Visitor<Result<LiteralType, Exit>> for Interpreter {
             },
             _ => {unreachable!()}
         }
-        return self.evaluate(&logical.right);
+        self.evaluate(&logical.right)
     }
 
     fn visit_call(&mut self, call : &expr::Call) -> Result<LiteralType, Exit> {
@@ -443,95 +661,116 @@ impl expr::Visitor<Result<LiteralType, Exit>> for Interpreter {
             args.push(self.evaluate(arg)?);
         }
 
-        if let LiteralType::Callable(Callable::LoxFunction(function)) = callee {
-            if args.len() as i32 != function.arity() {
-                // TODO: Report error for invalid number of arguments
-                return Err(Exit::RuntimeError(RuntimeError {
-                    token : call.paren.clone(),
-                    message : format!("Expected {} arguments but got {}", function.arity(), args.len())
-                }));
-            }   
-            return function.call(self, &args)
-        } else if let LiteralType::Callable(Callable::LoxCLass(class)) = callee {
-            if args.len() as i32 != class.arity() {
-                return Err(Exit::RuntimeError(RuntimeError {
-                    token : call.paren.clone(),
-                    message : format!("Expected {} arguments but got {}", class.arity(), args.len())
-                }));
-            }
-            return class.call(self, &args)
-        } {
-            Err(Exit::RuntimeError(
-                RuntimeError {
-                    token : call.paren.clone(),
-                    message : "Can only call functions and classes".to_string()
+        let callable : &dyn LoxCallable = match &callee {
+            LiteralType::Callable(Callable::LoxFunction(function)) => function,
+            LiteralType::Callable(Callable::LoxCLass(class)) => class,
+            LiteralType::Callable(Callable::NativeFunction(function)) => function,
+            LiteralType::Callable(Callable::NativeMethod(method)) => method,
+            _ => return Err(Exit::RuntimeError(Box::new(RuntimeError {
+                token : call.paren.clone(),
+                message : "Can only call functions and classes".to_string(),
+                trace : vec![]
+            }))),
+        };
+
+        if !callable.arity().accepts(args.len()) {
+            return Err(Exit::RuntimeError(Box::new(RuntimeError {
+                token : call.paren.clone(),
+                message : format!("Expected {} arguments but got {}", callable.arity(), args.len()),
+                trace : vec![]
+            })));
+        }
+
+        // map methods cannot call `hash()` themselves, so an instance key is
+        // worked out here and handed to the key method
+        if let LiteralType::Callable(Callable::NativeMethod(method)) = &callee {
+            if let (LiteralType::Map(map), Some(by_key)) = (&*method.receiver, map::key_method(&method.name)) {
+                if let Some(key) = self.hashed_key(map, &args[0], &call.paren)? {
+                    return Ok(by_key(map, &key));
                 }
-            ))
+            }
         }
+
+        // natives report their errors at this call
+        let enclosing_site = self.native_call_site.replace(call.paren.clone());
+        let result = self.call_in_frame(callable.name(), &call.paren, |interpreter| callable.call(interpreter, &args));
+        self.native_call_site = enclosing_site;
+        result
     }
 
     fn visit_get(&mut self, get : &expr::Get) -> Result<LiteralType, Exit> {
         let object = self.evaluate(&get.object)?;
+        if let Some(method) = value_method(&object, &get.name.lexeme) {
+            return method.map_err(|message| Exit::RuntimeError(Box::new(RuntimeError {
+                token : get.name.clone(),
+                message,
+                trace : vec![]
+            })));
+        }
         if let LiteralType::Callable(c) = object {
             match c {
                 Callable::LoxInstance(instance) => {
                     return instance.borrow().get(&get.name);
                 }
                 Callable::LoxCLass(class ) => {
-                    let func = class.find_method(get.name.lexeme.clone());
+                    let func = class.find_method(&get.name.lexeme);
                     if let Some (f) = func {
                         return Ok (LiteralType::Callable(Callable::LoxFunction(f.clone())))
                     } else {
-                        return Err ( Exit::RuntimeError(RuntimeError {
+                        return Err ( Exit::RuntimeError(Box::new(RuntimeError {
                             token : get.name.clone(),
-                            message : format!("Undefined static methods '{}' on class <{}>", get.name.lexeme, class.name, )
-                        }))
+                            message : format!("Undefined static methods '{}' on class <{}>", get.name.lexeme, class.name, ),
+                            trace : vec![]
+                        })))
                     }
                 }
                 _ => {}
             }
         }
-        Err(Exit::RuntimeError(
+        Err(Exit::RuntimeError(Box::new(
             RuntimeError {
                 token : get.name.clone(),
-                message : "Only instances have properties".to_string()
-            }
+                message : "Only instances have properties".to_string(),
+                trace : vec![]
+            })
         ))
     }
 
     fn visit_superr(&mut self, superr : &expr::Superr) -> Result<LiteralType, Exit> {
-        let distance = self.locals.get(&Expr::Superr(superr.clone()));
-
-        if distance.is_none() {
-            return Err(Exit::RuntimeError(RuntimeError {
+        let Some(&(distance, _)) = self.locals.get(&superr.id) else {
+            return Err(Exit::RuntimeError(Box::new(RuntimeError {
                 token : superr.method.clone(),
-                message : "Undefined variable".to_string()
-            }));
-        }
+                message : "Undefined variable".to_string(),
+                trace : vec![]
+            })));
+        };
 
-        let sup = self.environment.borrow_mut().get_at(*distance.unwrap() as i32, "super".to_string()).unwrap();
+        // `super` and `this` are each alone in their scope
+        let sup = self.environment.borrow().get_at(distance, 0);
 
-        let object = self.environment.borrow_mut().get_at(*distance.unwrap() as i32 - 1, "this".to_string()).unwrap();
+        let object = self.environment.borrow().get_at(distance - 1, 0);
 
         if let LiteralType::Callable(Callable::LoxCLass(c)) = sup {
             if let LiteralType::Callable(Callable::LoxInstance(instance)) = object {
-                let method = c.find_method(superr.method.lexeme.clone());
+                let method = c.find_method(&superr.method.lexeme);
                 if let Some (m) = method {
                     let func = m.bind(instance);
                     return Ok(LiteralType::Callable(Callable::LoxFunction(func)));
                 } else {
-                    return Err(Exit::RuntimeError(RuntimeError {
+                    return Err(Exit::RuntimeError(Box::new(RuntimeError {
                         token : superr.method.clone(),
-                        message : "Undefined property".to_string()
-                    }));
+                        message : "Undefined property".to_string(),
+                        trace : vec![]
+                    })));
                 }
             }
         
         }
-        return Err(Exit::RuntimeError(RuntimeError {
+        Err(Exit::RuntimeError(Box::new(RuntimeError {
             token : superr.method.clone(),
-            message : "Undefined property".to_string()
-        }));
+            message : "Undefined property".to_string(),
+            trace : vec![]
+        })))
     }
     
 
@@ -540,19 +779,91 @@ impl expr::Visitor<Result<LiteralType, Exit>> for Interpreter {
 
         if let LiteralType::Callable(Callable::LoxInstance(instance)) = object {
             let value = self.evaluate(&set.value)?;
-            instance.borrow_mut().set(&set.name, value.clone());
+            instance.borrow_mut().set(&set.name.lexeme, value.clone()).map_err(|message| Exit::RuntimeError(Box::new(RuntimeError {
+                token : set.name.clone(),
+                message,
+                trace : vec![]
+            })))?;
 
             return Ok(value);
         }
 
-        Err(Exit::RuntimeError(RuntimeError {
+        Err(Exit::RuntimeError(Box::new(RuntimeError {
             token : set.name.clone(),
-            message : "Only instances have fields".to_string()
-        }))
+            message : "Only instances have fields".to_string(),
+            trace : vec![]
+        })))
+    }
+
+    fn visit_list(&mut self, list : &expr::List) -> Result<LiteralType, Exit> {
+        let mut elements = Vec::with_capacity(list.elements.len());
+        for element in &list.elements {
+            elements.push(self.evaluate(element)?);
+        }
+        Ok(LiteralType::List(Rc::new(RefCell::new(elements))))
+    }
+
+    fn visit_index(&mut self, index : &expr::Index) -> Result<LiteralType, Exit> {
+        let object = self.evaluate(&index.object)?;
+        let key = self.evaluate(&index.index)?;
+        let hashed = match &object {
+            LiteralType::Map(map) => self.hashed_key(map, &key, &index.bracket)?,
+            _ => None,
+        };
+
+        get_index(&object, &key, hashed).map_err(|message| Exit::RuntimeError(Box::new(RuntimeError {
+            token : index.bracket.clone(),
+            message,
+            trace : vec![]
+        })))
+    }
+
+    fn visit_setindex(&mut self, setindex : &expr::SetIndex) -> Result<LiteralType, Exit> {
+        let object = self.evaluate(&setindex.object)?;
+        let key = self.evaluate(&setindex.index)?;
+        let value = self.evaluate(&setindex.value)?;
+        let hashed = match &object {
+            LiteralType::Map(map) => self.hashed_key(map, &key, &setindex.bracket)?,
+            _ => None,
+        };
+
+        set_index(&object, &key, hashed, value.clone()).map_err(|message| Exit::RuntimeError(Box::new(RuntimeError {
+            token : setindex.bracket.clone(),
+            message,
+            trace : vec![]
+        })))?;
+        Ok(value)
+    }
+
+    fn visit_map(&mut self, map : &expr::Map) -> Result<LiteralType, Exit> {
+        // every key and value is evaluated before any key is hashed, in the
+        // order the VM's `Op::Map` sees them
+        let mut pairs = vec![];
+        for (key, value) in map.keys.iter().zip(map.values.iter()) {
+            pairs.push((self.evaluate(key)?, self.evaluate(value)?));
+        }
+        let entries = Rc::new(RefCell::new(LoxMap::new()));
+        for (k, v) in pairs {
+            let k = match self.hashed_key(&entries, &k, &map.brace)? {
+                Some(k) => k,
+                None => MapKey::from_value(&k).map_err(|message| Exit::RuntimeError(Box::new(RuntimeError {
+                    token : map.brace.clone(),
+                    message,
+                    trace : vec![]
+                })))?,
+            };
+            entries.borrow_mut().insert(k, v);
+        }
+        Ok(LiteralType::Map(entries))
+    }
+
+    fn visit_lambda(&mut self, lambda : &expr::Lambda) -> Result<LiteralType, Exit> {
+        let f = LoxFunction::new(lambda.declaration.clone(), Rc::clone(&self.environment), false);
+        Ok(LiteralType::Callable(Callable::LoxFunction(f)))
     }
 
     fn visit_this(&mut self, this : &expr::This) -> Result<LiteralType, Exit> {
-        self.look_up_variable(this.keyword.clone(), &Expr::This(this.clone()))
+        self.look_up_variable(&this.keyword, this.id)
     }
 }
 
@@ -574,14 +885,14 @@ impl stmt::Visitor<Result<(), Exit>> for Interpreter {
             None => LiteralType::Nil,
         };
 
-        self.environment.borrow_mut().define(var.name.lexeme.clone(), value);
+        self.declare(&var.name.lexeme, value);
         Ok(())
     }
 
     fn visit_block(&mut self, block : &stmt::Block) -> Result<(), Exit> {
         let stmts = &block.statements;
         let environment = Environemnt::new(Some(self.environment.clone()));
-        self.execute_block(stmts, environment, self.in_loop)?;
+        self.execute_block(stmts, environment)?;
         Ok(())
     }
 
@@ -591,7 +902,7 @@ impl stmt::Visitor<Result<(), Exit>> for Interpreter {
             self.execute(&iff.then_branch)?;
         } else {
             if let Some (else_branch) = &iff.else_branch {
-                self.execute(&else_branch)?;
+                self.execute(else_branch)?;
             }     
         }
         Ok(())
@@ -602,59 +913,77 @@ impl stmt::Visitor<Result<(), Exit>> for Interpreter {
 
         while is_truthy(& self.evaluate(condition)?) {
 
-            self.in_loop = true;
+            if !continues(self.execute(&whilee.body), &whilee.label)? {
+                break;
+            }
+
+            if let Some(increment) = &whilee.increment {
+                self.evaluate(increment)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn visit_forin(&mut self, forin : &stmt::ForIn) -> Result<(), Exit> {
+        let iterable = self.evaluate(&forin.iterable)?;
+        let mut iter = LoxIter::new(self, iterable, &forin.keyword)?;
+
+        let previous = Rc::clone(&self.environment);
+        while let Some(value) = iter.next(self, &forin.keyword)? {
+            // fresh environment per iteration, so closures keep their own value
+            let mut environment = Environemnt::new(Some(Rc::clone(&previous)));
+            environment.push(value);
+            self.environment = Rc::new(RefCell::new(environment));
 
-            self.execute(&whilee.body)?;
+            let result = self.execute(&forin.body);
+            self.environment = Rc::clone(&previous);
 
-            if self.loop_break {
-                self.loop_break = false;
+            if !continues(result, &forin.label)? {
                 break;
             }
         }
 
-        self.in_loop = false;
         Ok(())
     }
 
-    fn visit_breakk(&mut self, _break : &stmt::Breakk) -> Result<(), Exit> {
-        self.loop_break = true;
-        Ok(())
+    fn visit_breakk(&mut self, breakk : &stmt::Breakk) -> Result<(), Exit> {
+        Err(Exit::Break(breakk.label.as_ref().map(|l| l.lexeme.clone())))
     }
 
-    fn visit_continuee(&mut self, _continue : &stmt::Continuee) -> Result<(), Exit> {
-        self.loop_continue = true;
-        Ok(())
+    fn visit_continuee(&mut self, continuee : &stmt::Continuee) -> Result<(), Exit> {
+        Err(Exit::Continue(continuee.label.as_ref().map(|l| l.lexeme.clone())))
     }
 
     fn visit_function(&mut self, function : &stmt::Function) -> Result<(), Exit> {
         let f = Callable::LoxFunction(LoxFunction::new(function.clone(), Rc::clone(&self.environment), false));
-        self.environment.borrow_mut().define(function.name.lexeme.clone(), LiteralType::Callable(f));
+        self.declare(&function.name.lexeme, LiteralType::Callable(f));
         Ok(())
     }
 
     fn visit_class(&mut self, class : &stmt::Class) -> Result<(), Exit> {
         
         let mut eval_class = LiteralType::Nil;
-        let mut s_c: Option<LoxCLass> = None;
+        let mut s_c: Option<Rc<LoxCLass>> = None;
 
-        if let Some (sc) = &class.SuperClass {
+        if let Some (sc) = &class.super_class {
             eval_class = self.evaluate(sc)?;
             if let LiteralType::Callable(Callable::LoxCLass(c)) = &eval_class {
-                s_c = Some(c.clone());
+                s_c = Some(Rc::clone(c));
             } else {
-                return Err(Exit::RuntimeError(RuntimeError {
+                return Err(Exit::RuntimeError(Box::new(RuntimeError {
                     token : class.name.clone(),
-                    message : "Superclass must be a class".to_string()
-                }));
+                    message : "Superclass must be a class".to_string(),
+                    trace : vec![]
+                })));
             }
         }
 
-        self.environment.borrow_mut().define(class.name.lexeme.to_string(), LiteralType::Nil);
+        let slot = self.declare(&class.name.lexeme, LiteralType::Nil);
 
-        if class.SuperClass.is_some() {
+        if class.super_class.is_some() {
             self.environment = Rc::new(RefCell::new(Environemnt::new(Some(Rc::clone(&self.environment)))));
-            self.environment.borrow_mut().
-                define("super".to_string(), eval_class);
+            self.environment.borrow_mut().push(eval_class);
         }
 
         let mut map = HashMap::new();
@@ -668,20 +997,58 @@ impl stmt::Visitor<Result<(), Exit>> for Interpreter {
         }
 
         let clas = LoxCLass {
-            name : class.name.lexeme.clone(),
+            name : class.name.lexeme.to_string(),
             methods : map,
-            super_class : s_c.map(Box::new),
+            super_class : s_c,
         };
 
-        if class.SuperClass.is_some() {
+        if class.super_class.is_some() {
             let prev = Rc::clone(self.environment.borrow_mut().previous.as_ref().unwrap());
             self.environment = prev;
         }
 
-        self.environment.borrow_mut().assign_at(0, clas.name.clone(),LiteralType::Callable(Callable::LoxCLass(clas)));
+        let value = LiteralType::Callable(Callable::LoxCLass(Rc::new(clas)));
+        match slot {
+            Some(slot) => self.environment.borrow_mut().assign_at(0, slot, value),
+            None => self.globals.borrow_mut().define(class.name.lexeme.clone(), value),
+        }
         Ok(())
     }
 
+    fn visit_throw(&mut self, throw : &stmt::Throw) -> Result<(), Exit> {
+        let value = self.evaluate(&throw.value)?;
+        Err(Exit::Throw(Box::new(Thrown {
+            value,
+            keyword : throw.keyword.clone(),
+            trace : vec![],
+        })))
+    }
+
+    fn visit_tryy(&mut self, tryy : &stmt::Tryy) -> Result<(), Exit> {
+        let environment = Environemnt::new(Some(Rc::clone(&self.environment)));
+        let mut result = self.execute_block(&tryy.body, environment);
+
+        if let (Some(_), Some(body)) = (&tryy.catch_name, &tryy.catch_body) {
+            // returns keep unwinding, thrown values and runtime errors are caught
+            let caught = match &result {
+                Err(Exit::Throw(thrown)) => Some(thrown.value.clone()),
+                Err(Exit::RuntimeError(e)) => Some(self.error_value(e)),
+                _ => None,
+            };
+            if let Some(value) = caught {
+                let mut environment = Environemnt::new(Some(Rc::clone(&self.environment)));
+                environment.push(value);
+                result = self.execute_block(body, environment);
+            }
+        }
+
+        if let Some(body) = &tryy.finally_body {
+            let environment = Environemnt::new(Some(Rc::clone(&self.environment)));
+            self.execute_block(body, environment)?;
+        }
+        result
+    }
+
     fn visit_returnn(&mut self, returnn : &stmt::Returnn) -> Result<(), Exit> {
         let value = match &returnn.value {
             Some (expr) => self.evaluate(expr)?,
@@ -0,0 +1,117 @@
+// ? File, stdin and process natives: `readFile`, `writeFile`, `appendFile`,
+// ? `readLine`, `input`, `exists`, `listDir` and `args`.
+// ? Every one of them fails with a runtime error (never a panic) and refuses
+// ? to run at all when the interpreter is sandboxed (`allow_io` off)
+
+use std::cell::RefCell;
+use std::fs;
+use std::io::Write;
+use std::rc::Rc;
+
+use crate::interpreter::Interpreter;
+use crate::loxcallable::{Arity, BuiltinFn};
+use crate::scanner::LiteralType;
+
+pub const NATIVES : [(&str, Arity, BuiltinFn); 8] = [
+    ("readFile", Arity::Exact(1), read_file),
+    ("writeFile", Arity::Exact(2), write_file),
+    ("appendFile", Arity::Exact(2), append_file),
+    ("readLine", Arity::Exact(0), read_line),
+    ("input", Arity::Range(0, 1), input),
+    ("exists", Arity::Exact(1), exists),
+    ("listDir", Arity::Exact(1), list_dir),
+    ("args", Arity::Exact(0), args),
+];
+
+fn allowed (interpreter : &Interpreter, function : &str) -> Result<(), String> {
+    if interpreter.allow_io {
+        Ok(())
+    } else {
+        Err(format!("{}() is not available in the sandbox", function))
+    }
+}
+
+fn path_arg<'a> (function : &str, value : &'a LiteralType) -> Result<&'a str, String> {
+    match value {
+        LiteralType::String(s) => Ok(s),
+        _ => Err(format!("{}() expects a path string but got {}", function, value.repr())),
+    }
+}
+
+fn read_file (interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    allowed(interpreter, "readFile")?;
+    let path = path_arg("readFile", &args[0])?;
+    fs::read_to_string(path)
+        .map(|s| LiteralType::String(s.into()))
+        .map_err(|e| format!("Cannot read '{}': {}", path, e))
+}
+
+// * Replaces the file's contents, creating it if needed
+fn write_file (interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    allowed(interpreter, "writeFile")?;
+    let path = path_arg("writeFile", &args[0])?;
+    fs::write(path, args[1].to_string())
+        .map(|_| LiteralType::Nil)
+        .map_err(|e| format!("Cannot write '{}': {}", path, e))
+}
+
+fn append_file (interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    allowed(interpreter, "appendFile")?;
+    let path = path_arg("appendFile", &args[0])?;
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(args[1].to_string().as_bytes()))
+        .map(|_| LiteralType::Nil)
+        .map_err(|e| format!("Cannot append to '{}': {}", path, e))
+}
+
+// * One line from the interpreter's input without its line ending, nil once
+// * the input is exhausted
+fn read_line (interpreter : &mut Interpreter, _args : &[LiteralType]) -> Result<LiteralType, String> {
+    allowed(interpreter, "readLine")?;
+    match interpreter.read_line() {
+        Ok(Some(line)) => Ok(LiteralType::String(line.into())),
+        Ok(None) => Ok(LiteralType::Nil),
+        Err(e) => Err(format!("Cannot read from stdin: {}", e)),
+    }
+}
+
+// * Prints the optional prompt without a newline, then reads a line like `readLine`
+fn input (interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    allowed(interpreter, "input")?;
+    let prompt = args.first().map_or(String::new(), LiteralType::to_string);
+    let output = interpreter.output();
+    write!(output, "{}", prompt)
+        .and_then(|_| output.flush())
+        .map_err(|e| format!("Cannot write to stdout: {}", e))?;
+    read_line(interpreter, &[])
+}
+
+fn exists (interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    allowed(interpreter, "exists")?;
+    let path = path_arg("exists", &args[0])?;
+    Ok(LiteralType::Bool(std::path::Path::new(path).exists()))
+}
+
+// * Entry names in `path`, sorted so scripts see the same order everywhere
+fn list_dir (interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    allowed(interpreter, "listDir")?;
+    let path = path_arg("listDir", &args[0])?;
+    let error = |e : std::io::Error| format!("Cannot list '{}': {}", path, e);
+    let mut names = vec![];
+    for entry in fs::read_dir(path).map_err(error)? {
+        names.push(entry.map_err(error)?.file_name().to_string_lossy().to_string());
+    }
+    names.sort();
+    let names = names.into_iter().map(|s| LiteralType::String(s.into())).collect();
+    Ok(LiteralType::List(Rc::new(RefCell::new(names))))
+}
+
+// * Command-line arguments given after the script name
+fn args (interpreter : &mut Interpreter, _args : &[LiteralType]) -> Result<LiteralType, String> {
+    allowed(interpreter, "args")?;
+    let args = interpreter.script_args.iter().cloned().map(|s| LiteralType::String(s.into())).collect();
+    Ok(LiteralType::List(Rc::new(RefCell::new(args))))
+}
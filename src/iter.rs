@@ -0,0 +1,76 @@
+// ? What `for (x in iterable)` walks over: list elements, map keys, string
+// ? characters, numeric ranges, or instances implementing `iterator()`,
+// ? whose result is asked for `next()` until it returns nil
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::error_handler::RuntimeError;
+use crate::interpreter::{Exit, Interpreter};
+use crate::loxcallable::{Callable, LoxInstance};
+use crate::scanner::{LiteralType, Token};
+
+pub enum LoxIter {
+    // * indexed on every step, so elements pushed inside the loop are visited
+    List (Rc<RefCell<Vec<LiteralType>>>, usize),
+    // * map keys and string characters, taken when the loop starts
+    Items (std::vec::IntoIter<LiteralType>),
+    Range (f64, f64),
+    Instance (Rc<RefCell<LoxInstance>>),
+}
+
+impl LoxIter {
+    pub fn new (interpreter : &mut Interpreter, iterable : LiteralType, keyword : &Token) -> Result<LoxIter, Exit> {
+        match iterable {
+            LiteralType::List(l) => Ok(LoxIter::List(l, 0)),
+            LiteralType::Map(m) => Ok(LoxIter::Items(m.borrow().keys().into_iter())),
+            LiteralType::String(s) => {
+                let chars = s.chars().map(|c| LiteralType::String(c.to_string().into())).collect::<Vec<LiteralType>>();
+                Ok(LoxIter::Items(chars.into_iter()))
+            }
+            LiteralType::Range(start, end) => Ok(LoxIter::Range(start, end)),
+            LiteralType::Callable(Callable::LoxInstance(instance)) => {
+                match interpreter.call_method(&instance, "iterator", &[], keyword)? {
+                    Some(LiteralType::Callable(Callable::LoxInstance(iterator))) => Ok(LoxIter::Instance(iterator)),
+                    Some(_) => Err(error(keyword, "'iterator()' must return an instance")),
+                    None => Err(error(keyword, "Instance has no 'iterator()' method")),
+                }
+            }
+            _ => Err(error(keyword, "Can only iterate over lists, maps, strings, ranges and iterable instances")),
+        }
+    }
+
+    pub fn next (&mut self, interpreter : &mut Interpreter, keyword : &Token) -> Result<Option<LiteralType>, Exit> {
+        match self {
+            LoxIter::List(l, i) => {
+                let value = l.borrow().get(*i).cloned();
+                *i += 1;
+                Ok(value)
+            }
+            LoxIter::Items(items) => Ok(items.next()),
+            LoxIter::Range(current, end) => {
+                if current >= end {
+                    return Ok(None);
+                }
+                let value = *current;
+                *current += 1.0;
+                Ok(Some(LiteralType::Number(value)))
+            }
+            LoxIter::Instance(iterator) => {
+                match interpreter.call_method(iterator, "next", &[], keyword)? {
+                    Some(LiteralType::Nil) => Ok(None),
+                    Some(value) => Ok(Some(value)),
+                    None => Err(error(keyword, "Iterator has no 'next()' method")),
+                }
+            }
+        }
+    }
+}
+
+fn error (keyword : &Token, message : &str) -> Exit {
+    Exit::RuntimeError(Box::new(RuntimeError {
+        token : keyword.clone(),
+        message : message.to_string(),
+        trace : vec![]
+    }))
+}
@@ -0,0 +1,27 @@
+// relative modules
+pub mod scanner;
+pub mod symbol;
+pub mod error_handler;
+pub mod expr;
+pub mod parser;
+pub mod traits;
+pub mod interpreter;
+pub mod stmt;
+pub mod environemnt;
+pub mod loxcallable;
+pub mod resolver;
+pub mod list;
+pub mod map;
+pub mod string;
+pub mod math;
+pub mod io;
+pub mod iter;
+pub mod lox;
+pub mod render;
+pub mod chunk;
+pub mod compiler;
+pub mod vm;
+
+pub mod tests;
+
+pub use lox::{Backend, Lox, RunResult, STACK_SIZE};
@@ -0,0 +1,86 @@
+// ? Native methods available on list values: `xs.push(1)`, `xs.len()`, ...
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::interpreter::Interpreter;
+use crate::loxcallable::{Arity, NativeMethod, NativeMethodFn};
+use crate::scanner::LiteralType;
+
+// * Looks up `name` on `list`, returning the method bound to it
+pub fn method (list : &Rc<RefCell<Vec<LiteralType>>>, name : &str) -> Option<NativeMethod> {
+    let (arity, function) : (usize, NativeMethodFn) = match name {
+        "push" => (1, push),
+        "pop" => (0, pop),
+        "len" => (0, len),
+        "insert" => (2, insert),
+        "slice" => (2, slice),
+        _ => return None,
+    };
+    Some(NativeMethod {
+        name : name.to_string(),
+        receiver : Box::new(LiteralType::List(Rc::clone(list))),
+        arity : Arity::Exact(arity),
+        function,
+    })
+}
+
+fn items (receiver : &LiteralType) -> &Rc<RefCell<Vec<LiteralType>>> {
+    match receiver {
+        LiteralType::List(l) => l,
+        _ => unreachable!("list method bound to a non-list"),
+    }
+}
+
+// * Converts `value` into an index in `0..=max`
+pub fn to_index (value : &LiteralType, max : usize) -> Result<usize, String> {
+    match value {
+        LiteralType::Number(n) if n.fract() == 0.0 => {
+            if *n < 0.0 || *n as usize > max {
+                return Err(format!("Index {} out of bounds", n));
+            }
+            Ok(*n as usize)
+        }
+        _ => Err("Index must be an integer".to_string()),
+    }
+}
+
+// * Converts `value` into the index of an existing element
+pub fn element_index (value : &LiteralType, len : usize) -> Result<usize, String> {
+    let index = to_index(value, len)?;
+    if index == len {
+        return Err(format!("Index {} out of bounds", index));
+    }
+    Ok(index)
+}
+
+fn push (_interpreter : &mut Interpreter, receiver : &LiteralType, args : &[LiteralType]) -> Result<LiteralType, String> {
+    items(receiver).borrow_mut().push(args[0].clone());
+    Ok(LiteralType::Nil)
+}
+
+fn pop (_interpreter : &mut Interpreter, receiver : &LiteralType, _args : &[LiteralType]) -> Result<LiteralType, String> {
+    items(receiver).borrow_mut().pop().ok_or("Cannot pop from an empty list".to_string())
+}
+
+fn len (_interpreter : &mut Interpreter, receiver : &LiteralType, _args : &[LiteralType]) -> Result<LiteralType, String> {
+    Ok(LiteralType::Number(items(receiver).borrow().len() as f64))
+}
+
+fn insert (_interpreter : &mut Interpreter, receiver : &LiteralType, args : &[LiteralType]) -> Result<LiteralType, String> {
+    let mut list = items(receiver).borrow_mut();
+    let index = to_index(&args[0], list.len())?;
+    list.insert(index, args[1].clone());
+    Ok(LiteralType::Nil)
+}
+
+// * Elements in `start..end`, as a new list
+fn slice (_interpreter : &mut Interpreter, receiver : &LiteralType, args : &[LiteralType]) -> Result<LiteralType, String> {
+    let list = items(receiver).borrow();
+    let start = to_index(&args[0], list.len())?;
+    let end = to_index(&args[1], list.len())?;
+    if start > end {
+        return Err("Slice start must not be after its end".to_string());
+    }
+    Ok(LiteralType::List(Rc::new(RefCell::new(list[start..end].to_vec()))))
+}
@@ -0,0 +1,183 @@
+// ? Embedding API: runs a source string through every stage of the pipeline
+// ? (scanner -> parser -> resolver -> interpreter) and hands the outcome back
+// ? to the caller instead of printing it. The last stage is either the
+// ? tree-walking interpreter or the bytecode VM, see `Backend`
+
+use std::io::{BufRead, Write};
+
+use crate::compiler::Compiler;
+use crate::error_handler::{DiagnosticSink, RuntimeError};
+use crate::interpreter::{Exit, Interpreter};
+use crate::loxcallable::Callable;
+use crate::parser::{NodeId, Parser};
+use crate::resolver::Resolver;
+use crate::scanner::{LiteralType, Scanner};
+use crate::symbol::Symbol;
+use crate::vm::Vm;
+
+// ? What runs the resolved program. Both give the same output for the same
+// ? source; the VM still uses the interpreter for globals, natives and I/O
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    #[default]
+    TreeWalk,
+    Vm,
+}
+
+impl Backend {
+    pub fn parse (s : &str) -> Option<Backend> {
+        match s {
+            "tree" => Some(Backend::TreeWalk),
+            "vm" => Some(Backend::Vm),
+            _ => None,
+        }
+    }
+}
+
+/// Stack a thread running `Lox` needs for the tree-walking backend to reach
+/// `interpreter::FRAMES_MAX` nested calls, and report a "Stack overflow"
+/// error, before it runs out of native stack. The binary runs on such a thread
+pub const STACK_SIZE : usize = 512 * 1024 * 1024;
+
+pub struct Lox {
+    pub repl : bool,
+    pub backend : Backend,
+    interpreter : Interpreter,
+    vm : Vm,
+    // first node id the next run's parser hands out; the interpreter keeps
+    // resolutions from earlier runs, so ids must never repeat
+    next_id : NodeId,
+}
+
+/// Outcome of a single `Lox::run` call.
+#[derive(Debug, Default)]
+pub struct RunResult {
+    /// Value of the last expression statement, if the program ended on one
+    pub value : Option<LiteralType>,
+    /// Static errors (scanning, parsing, resolving)
+    pub diagnostics : DiagnosticSink,
+    pub runtime_error : Option<RuntimeError>,
+}
+
+impl RunResult {
+    pub fn is_ok (&self) -> bool {
+        self.diagnostics.is_empty() && self.runtime_error.is_none()
+    }
+}
+
+// * Error instances report their message, anything else is printed as is
+fn describe_thrown (value : &LiteralType) -> String {
+    if let LiteralType::Callable(Callable::LoxInstance(instance)) = value {
+        if let Some(message) = instance.borrow().field("message") {
+            return message.to_string();
+        }
+    }
+    if let LiteralType::Callable(Callable::VmInstance(instance)) = value {
+        if let Some(message) = instance.borrow().fields.get(&Symbol::intern("message")) {
+            return message.to_string();
+        }
+    }
+    value.to_string()
+}
+
+impl Default for Lox {
+    fn default () -> Lox {
+        Lox::new()
+    }
+}
+
+impl Lox {
+
+    pub fn new () -> Lox {
+        Lox {
+            repl : false,
+            backend : Backend::default(),
+            interpreter : Interpreter::new(),
+            vm : Vm::new(),
+            next_id : NodeId::default(),
+        }
+    }
+
+    // * Program output and input go through the given streams, see `Interpreter::with_io`
+    pub fn with_io (stdout : Box<dyn Write>, stdin : Box<dyn BufRead>) -> Lox {
+        Lox {
+            repl : false,
+            backend : Backend::default(),
+            interpreter : Interpreter::with_io(stdout, stdin),
+            vm : Vm::new(),
+            next_id : NodeId::default(),
+        }
+    }
+
+    pub fn interpreter (&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+
+    pub fn run (&mut self, source : &str) -> RunResult {
+        self.run_source(source, None)
+    }
+
+    // * Same as `run`, but diagnostics are tagged with the file the source came from
+    pub fn run_source (&mut self, source : &str, file : Option<&str>) -> RunResult {
+        let mut result = self.run_stages(source);
+        if let Some(file) = file {
+            result.diagnostics.set_file(file);
+        }
+        result
+    }
+
+    fn run_stages (&mut self, source : &str) -> RunResult {
+        let mut result = RunResult::default();
+
+        let mut scanner = Scanner::new(source.to_string());
+        let (tokens, scan_errors) = scanner.scan_tokens();
+        for e in scan_errors {
+            result.diagnostics.push(e);
+        }
+
+        // parsed even after scanner errors, to report its own errors too
+        let mut parser = Parser::continuing(tokens, self.next_id);
+        let parsed = parser.parse();
+        self.next_id = parser.next_id();
+        // a half-scanned or half-parsed program never runs
+        let stmts = match parsed {
+            Ok(stmts) if !result.diagnostics.has_errors() => stmts,
+            Ok(_) => return result,
+            Err(errors) => {
+                for e in errors {
+                    result.diagnostics.push(e);
+                }
+                return result;
+            }
+        };
+
+        let mut resolver = Resolver::new(&mut self.interpreter);
+        resolver.resolve(&stmts);
+        if resolver.had_error() {
+            for e in resolver.take_errors() {
+                result.diagnostics.push(e);
+            }
+            return result;
+        }
+
+        let outcome = match self.backend {
+            Backend::TreeWalk => self.interpreter.interpret(stmts, self.repl),
+            Backend::Vm => {
+                let script = Compiler::compile(&stmts, self.repl);
+                self.vm.run(&mut self.interpreter, script)
+            }
+        };
+        match outcome {
+            Ok(value) => result.value = value,
+            Err(Exit::Return(value)) => result.value = Some(value),
+            Err(Exit::RuntimeError(e)) => result.runtime_error = Some(*e),
+            Err(Exit::Throw(thrown)) => result.runtime_error = Some(RuntimeError {
+                token : thrown.keyword,
+                message : format!("Uncaught exception: {}", describe_thrown(&thrown.value)),
+                trace : thrown.trace,
+            }),
+            Err(Exit::Break(_)) | Err(Exit::Continue(_)) => unreachable!("the resolver rejects break and continue outside loops"),
+        }
+        result
+    }
+}
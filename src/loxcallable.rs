@@ -1,8 +1,8 @@
 
 use std::collections::HashMap;
 use std::fmt::{Display, Debug};
-use std::rc::Rc;
-use std::cell::{Ref, RefCell};
+use std::rc::{Rc, Weak};
+use std::cell::RefCell;
 
 use crate::environemnt::Environemnt;
 use crate::error_handler::RuntimeError;
@@ -10,52 +10,145 @@ use crate::scanner::{LiteralType, Token};
 use crate::interpreter::Interpreter;
 use crate::stmt::{Function};
 use crate::interpreter::Exit;
-use crate::Lox;
+use crate::symbol::Symbol;
+use crate::vm;
 
 #[derive(Debug, Clone)]
 pub enum Callable {
     LoxFunction (LoxFunction),
     NativeFunction (NativeFunction),
-    LoxCLass (LoxCLass),
+    NativeMethod (NativeMethod),
+    LoxCLass (Rc<LoxCLass>),
     LoxInstance (Rc<RefCell<LoxInstance>>),
+    // values only the `vm` backend creates
+    VmClosure (Rc<vm::Closure>),
+    VmBoundMethod (Rc<vm::BoundMethod>),
+    VmClass (Rc<vm::VmClass>),
+    VmInstance (Rc<RefCell<vm::VmInstance>>),
 }
 
 #[derive(Clone, Debug)]
 pub struct LoxFunction {
-    pub declaration : Box<Function>,
+    // shared by every closure and bound method made from the declaration
+    pub declaration : Rc<Function>,
     pub closure : Rc<RefCell<Environemnt>>,
     pub is_initializer : bool,
+    // the instance a bound method's `this` is
+    pub receiver : Option<Rc<RefCell<LoxInstance>>>,
 }
 #[derive(Debug, Clone)]
 pub struct LoxCLass {
     pub name : String,
-    pub methods : HashMap<String, LoxFunction>,
-    pub super_class : Option<Box<LoxCLass>>,
+    pub methods : HashMap<Symbol, LoxFunction>,
+    pub super_class : Option<Rc<LoxCLass>>,
 }
 
-#[derive(Debug, Clone)]
+// ? Only ever lives inside the `Rc` it was created in, see `LoxInstance::new`
+#[derive(Debug)]
 pub struct LoxInstance {
     class : Rc<LoxCLass>,
-    fields : HashMap<String, LiteralType>,
+    fields : HashMap<Symbol, LiteralType>,
+    // that `Rc`, so bound methods share this instance instead of a copy
+    this : Weak<RefCell<LoxInstance>>,
+    // native namespaces such as `Math`: Lox code can read fields but not set them
+    read_only : bool,
 }
-#[derive(Clone, Debug)]
+// ? Function implemented in Rust (`clock()`, `Math.sqrt`, or whatever an
+// ? embedder registers). It may capture Rust state, and errors unwind like
+// ? any other `Exit`; `Interpreter::native_error` builds one at the call site
+pub type NativeFn = Rc<dyn Fn (&mut Interpreter, &[LiteralType]) -> Result<LiteralType, Exit>>;
+
+// ? Plain built-in whose errors are messages, reported at the call site
+pub type BuiltinFn = fn (&mut Interpreter, &[LiteralType]) -> Result<LiteralType, String>;
+
+// ? How many arguments a native accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Exact (usize),
+    // * inclusive on both ends
+    Range (usize, usize),
+    // * at least this many
+    Variadic (usize),
+}
+
+impl Arity {
+    pub fn accepts (&self, count : usize) -> bool {
+        match *self {
+            Arity::Exact(n) => count == n,
+            Arity::Range(min, max) => min <= count && count <= max,
+            Arity::Variadic(min) => count >= min,
+        }
+    }
+}
+
+impl Display for Arity {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Arity::Exact(n) => write!(f, "{}", n),
+            Arity::Range(min, max) => write!(f, "{} to {}", min, max),
+            Arity::Variadic(min) => write!(f, "at least {}", min),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct NativeFunction {
     pub name : String,
-    pub arity : i32,
-    pub function : fn (&mut Interpreter, &Vec<LiteralType>) -> LiteralType,
+    pub arity : Arity,
+    pub function : NativeFn,
+}
+
+impl NativeFunction {
+    pub fn new (name : &str, arity : Arity, function : impl Fn (&mut Interpreter, &[LiteralType]) -> Result<LiteralType, Exit> + 'static) -> NativeFunction {
+        NativeFunction {
+            name : name.to_string(),
+            arity,
+            function : Rc::new(function),
+        }
+    }
+
+    pub fn builtin (name : &str, arity : Arity, function : BuiltinFn) -> NativeFunction {
+        NativeFunction::new(name, arity, move |interpreter, args| {
+            function(interpreter, args).map_err(|message| interpreter.native_error(message))
+        })
+    }
+}
+
+impl Debug for NativeFunction {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("NativeFunction")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
+}
+
+// ? Built-in method looked up on a value (`xs.push`), bound to that value.
+// ? Errors are plain messages, reported at the call site by the interpreter
+pub type NativeMethodFn = fn (&mut Interpreter, &LiteralType, &[LiteralType]) -> Result<LiteralType, String>;
+
+#[derive(Clone, Debug)]
+pub struct NativeMethod {
+    pub name : String,
+    pub receiver : Box<LiteralType>,
+    pub arity : Arity,
+    pub function : NativeMethodFn,
 }
 
 pub trait LoxCallable {
-    fn call (&self, interpreter : &mut Interpreter, arguments : &Vec<LiteralType>) -> Result<LiteralType, Exit>;
-    fn arity (&self) -> i32;
+    fn call (&self, interpreter : &mut Interpreter, arguments : &[LiteralType]) -> Result<LiteralType, Exit>;
+    fn arity (&self) -> Arity;
+    // * How the callee shows up in stack traces
+    fn name (&self) -> String;
 }
 
 impl LoxFunction {
     pub fn new (declaration : Function, closure : Rc<RefCell<Environemnt>>, is_init : bool) -> LoxFunction {
         LoxFunction {
-            declaration : Box::new(declaration),
-            closure : closure,
+            declaration : Rc::new(declaration),
+            closure,
             is_initializer : is_init,
+            receiver : None,
         }
     }
 
@@ -63,32 +156,30 @@ impl LoxFunction {
         let env = Rc::new(RefCell::new(Environemnt::new (
             Some (Rc::clone(&self.closure))
         )));
-        env.borrow_mut().define(
-            "this".to_string(), 
-            LiteralType::Callable(Callable::LoxInstance(instance))
-        );
+        env.borrow_mut().push(LiteralType::Callable(Callable::LoxInstance(Rc::clone(&instance))));
 
         LoxFunction {
-            declaration : self.declaration.clone(),
+            declaration : Rc::clone(&self.declaration),
             closure : env,
             is_initializer : self.is_initializer,
+            receiver : Some(instance),
         }
     }
 }
 
 impl LoxCallable for LoxFunction {
-    fn call (&self, interpreter : &mut Interpreter, arguments : &Vec<LiteralType>) -> Result<LiteralType, Exit> {
+    fn call (&self, interpreter : &mut Interpreter, arguments : &[LiteralType]) -> Result<LiteralType, Exit> {
         let mut env = Environemnt::new(Some(Rc::clone(&self.closure)));
 
-        for (i, param) in self.declaration.params.iter().enumerate() {
-            env.define(param.lexeme.clone(), arguments[i].clone());
+        for argument in arguments {
+            env.push(argument.clone());
         }
-        let res = interpreter.execute_block(&self.declaration.body,  env, false);
+        let res = interpreter.execute_block(&self.declaration.body,  env);
 
         match res {
             Ok (_) => Ok(
                 if self.is_initializer {
-                    self.closure.borrow_mut().get_at(0, "this".to_string()).unwrap()
+                    self.closure.borrow().get_at(0, 0)
                 } else {
                     LiteralType::Nil
                 }
@@ -97,20 +188,24 @@ impl LoxCallable for LoxFunction {
                 match e {
                     Exit::Return(v) => {
                         if self.is_initializer {
-                            return Ok(self.closure.borrow_mut().get_at(0, "this".to_string()).unwrap())
+                            return Ok(self.closure.borrow().get_at(0, 0))
                         }
-                        return Ok(v.clone())
+                        Ok(v.clone())
                     },
                     _ => {
-                        return Err(e)
+                        Err(e)
                     }
                 }
             }
         }
     }
 
-    fn arity (&self) -> i32 {
-        return self.declaration.params.len() as i32;
+    fn arity (&self) -> Arity {
+        Arity::Exact(self.declaration.params.len())
+    }
+
+    fn name (&self) -> String {
+        self.declaration.name.lexeme.to_string()
     }
 }
 
@@ -122,9 +217,9 @@ impl LoxCLass {
     //     }
     // }
 
-    pub fn find_method (&self, name : String) -> Option<&LoxFunction> {
-        if self.methods.contains_key(&name) {
-            return Some(self.methods.get(&name).unwrap());
+    pub fn find_method (&self, name : &Symbol) -> Option<&LoxFunction> {
+        if let Some(method) = self.methods.get(name) {
+            return Some(method);
         }
         if let Some(sup) = &self.super_class {
             return sup.find_method(name);
@@ -133,17 +228,12 @@ impl LoxCLass {
     }
 }
 
-// constructor for classes
-impl LoxCallable for LoxCLass {
-    fn call (&self, interpreter : &mut Interpreter, arguments : &Vec<LiteralType>) -> Result<LiteralType, Exit> {
-        let inst = Rc::new(RefCell::new(
-            LoxInstance {
-                class : Rc::new(self.clone()),
-                fields : HashMap::new(),
-            }
-        ));
+// constructor for classes; instances share the class they were made from
+impl LoxCallable for Rc<LoxCLass> {
+    fn call (&self, interpreter : &mut Interpreter, arguments : &[LiteralType]) -> Result<LiteralType, Exit> {
+        let inst = LoxInstance::new(Rc::clone(self));
 
-        if let Some (init) = self.find_method("init".to_string()) {
+        if let Some (init) = self.find_method(&Symbol::intern("init")) {
             let outer = init.bind(Rc::clone(&inst));
             outer.call(interpreter, arguments)?;
         }
@@ -152,32 +242,104 @@ impl LoxCallable for LoxCLass {
         )))
     }
 
-    fn arity (&self) -> i32 {
-        if let Some (init) = self.find_method("init".to_string()) {
+    fn arity (&self) -> Arity {
+        if let Some (init) = self.find_method(&Symbol::intern("init")) {
             return init.arity();
         }
-        return 0;
+        Arity::Exact(0)
+    }
+
+    fn name (&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl LoxCallable for NativeFunction {
+    fn call (&self, interpreter : &mut Interpreter, arguments : &[LiteralType]) -> Result<LiteralType, Exit> {
+        (self.function)(interpreter, arguments)
+    }
+
+    fn arity (&self) -> Arity {
+        self.arity
+    }
+
+    fn name (&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl LoxCallable for NativeMethod {
+    fn call (&self, interpreter : &mut Interpreter, arguments : &[LiteralType]) -> Result<LiteralType, Exit> {
+        (self.function)(interpreter, &self.receiver, arguments).map_err(|message| interpreter.native_error(message))
+    }
+
+    fn arity (&self) -> Arity {
+        self.arity
+    }
+
+    fn name (&self) -> String {
+        self.name.clone()
     }
 }
 
 impl LoxInstance {
+    pub fn new (class : Rc<LoxCLass>) -> Rc<RefCell<LoxInstance>> {
+        Rc::new_cyclic(|this| RefCell::new(LoxInstance {
+            class,
+            fields : HashMap::new(),
+            this : this.clone(),
+            read_only : false,
+        }))
+    }
+
+    // * The shared handle to this instance
+    pub fn handle (&self) -> Rc<RefCell<LoxInstance>> {
+        self.this.upgrade().expect("instance outlived its own Rc")
+    }
+
+    pub fn field (&self, name : &str) -> Option<LiteralType> {
+        self.fields.get(&Symbol::intern(name)).cloned()
+    }
+
+    pub fn fields (&self) -> &HashMap<Symbol, LiteralType> {
+        &self.fields
+    }
+
+    pub fn define (&mut self, name : &str, value : LiteralType) {
+        self.fields.insert(Symbol::intern(name), value);
+    }
+
+    pub fn find_method (&self, name : &str) -> Option<LoxFunction> {
+        self.class.find_method(&Symbol::intern(name)).cloned()
+    }
+
     pub fn get (&self, name : &Token) -> Result<LiteralType, Exit> {
-        if self.fields.contains_key(&name.lexeme) {
-            return Ok(self.fields.get(&name.lexeme).unwrap().clone());
+        if let Some(value) = self.fields.get(&name.lexeme) {
+            return Ok(value.clone());
         }
 
-        if let Some(method) = self.class.find_method(name.lexeme.clone()) {
-            let func = method.bind(Rc::new(RefCell::new(self.to_owned())));
+        if let Some(method) = self.class.find_method(&name.lexeme) {
+            let func = method.bind(self.handle());
             return Ok(LiteralType::Callable(Callable::LoxFunction(func)));
         }
 
-        Err (Exit::RuntimeError(RuntimeError {
+        Err (Exit::RuntimeError(Box::new(RuntimeError {
             token : name.clone(),
-            message : format!("Undefined property '{}'", name.lexeme)
-        }))
+            message : format!("Undefined property '{}'", name.lexeme),
+            trace : vec![]
+        })))
+    }
+    // * Field assignment from Lox code, refused on read-only instances
+    pub fn set (&mut self, name : &Symbol, value : LiteralType) -> Result<(), String> {
+        if self.read_only {
+            return Err(format!("Cannot set '{}': {} is read-only", name, self.class.name));
+        }
+        self.fields.insert(name.clone(), value);
+        Ok(())
     }
-    pub fn set (&mut self, name : &Token, value : LiteralType) {
-        self.fields.insert(name.lexeme.clone(), value);
+
+    pub fn freeze (&mut self) {
+        self.read_only = true;
     }
 }
 
@@ -193,19 +355,91 @@ impl Display for NativeFunction {
     }
 }
 
+impl Display for NativeMethod {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "<native method: {}>", self.name)
+    }
+}
+
 impl Display for LoxCLass {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "<class: {}>", self.name)
     }
 }
 
+// ? Objects are equal only to themselves (unless a class defines `equals`).
+// ? A function value is the same object as long as it comes from the same
+// ? declaration closing over the same scope. A bound method, Lox or native, is
+// ? the same as long as both its receiver and its method are
+impl Callable {
+    pub fn same (&self, other : &Callable) -> bool {
+        match (self, other) {
+            (Callable::LoxFunction(a), Callable::LoxFunction(b)) => {
+                Rc::ptr_eq(&a.declaration, &b.declaration) && match (&a.receiver, &b.receiver) {
+                    // `bind` closes each over a scope of its own holding `this`
+                    (Some(x), Some(y)) => Rc::ptr_eq(x, y) && same_scope(&a.closure.borrow().previous, &b.closure.borrow().previous),
+                    (None, None) => Rc::ptr_eq(&a.closure, &b.closure),
+                    _ => false,
+                }
+            }
+            (Callable::NativeFunction(a), Callable::NativeFunction(b)) => Rc::ptr_eq(&a.function, &b.function),
+            (Callable::NativeMethod(a), Callable::NativeMethod(b)) => a.name == b.name && same_receiver(&a.receiver, &b.receiver),
+            (Callable::LoxCLass(a), Callable::LoxCLass(b)) => Rc::ptr_eq(a, b),
+            (Callable::LoxInstance(a), Callable::LoxInstance(b)) => Rc::ptr_eq(a, b),
+            (Callable::VmClosure(a), Callable::VmClosure(b)) => Rc::ptr_eq(a, b),
+            (Callable::VmBoundMethod(a), Callable::VmBoundMethod(b)) => Rc::ptr_eq(&a.method, &b.method) && same_receiver(&a.receiver, &b.receiver),
+            (Callable::VmClass(a), Callable::VmClass(b)) => Rc::ptr_eq(a, b),
+            (Callable::VmInstance(a), Callable::VmInstance(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    // * Stands for the object when hashing; objects that are the `same` share it
+    pub fn address (&self) -> usize {
+        match self {
+            Callable::LoxFunction(f) => Rc::as_ptr(&f.declaration) as usize,
+            Callable::NativeFunction(f) => Rc::as_ptr(&f.function) as *const () as usize,
+            Callable::NativeMethod(m) => m.function as usize,
+            Callable::LoxCLass(c) => Rc::as_ptr(c) as usize,
+            Callable::LoxInstance(i) => Rc::as_ptr(i) as usize,
+            Callable::VmClosure(c) => Rc::as_ptr(c) as usize,
+            Callable::VmBoundMethod(m) => Rc::as_ptr(&m.method) as usize,
+            Callable::VmClass(c) => Rc::as_ptr(c) as usize,
+            Callable::VmInstance(i) => Rc::as_ptr(i) as usize,
+        }
+    }
+}
+
+fn same_scope (a : &Option<Rc<RefCell<Environemnt>>>, b : &Option<Rc<RefCell<Environemnt>>>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+        _ => false,
+    }
+}
+
+// * Whether two bound methods are bound to the same value
+fn same_receiver (a : &LiteralType, b : &LiteralType) -> bool {
+    match (a, b) {
+        (LiteralType::List(a), LiteralType::List(b)) => Rc::ptr_eq(a, b),
+        (LiteralType::Map(a), LiteralType::Map(b)) => Rc::ptr_eq(a, b),
+        (LiteralType::String(a), LiteralType::String(b)) => a == b,
+        (LiteralType::Callable(a), LiteralType::Callable(b)) => a.same(b),
+        _ => false,
+    }
+}
+
 impl Display for Callable {
     fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         let s = match self {
             Callable::LoxCLass(c) => c.to_string(),
             Callable::LoxFunction(f) => f.to_string(),
             Callable::NativeFunction(f) => f.to_string(),
+            Callable::NativeMethod(m) => m.to_string(),
             Callable::LoxInstance(i) => i.borrow().to_string(),
+            Callable::VmClosure(c) => c.to_string(),
+            Callable::VmBoundMethod(m) => m.to_string(),
+            Callable::VmClass(c) => c.to_string(),
+            Callable::VmInstance(i) => i.borrow().to_string(),
         };
         write!(f, "{}", s)
     }
@@ -1,132 +1,170 @@
 use std::env;
 use std::env::current_dir;
 use std::fs;
-use std::io::Write;
+use std::io::{IsTerminal, Write};
 use std::path::PathBuf;
 
-use error_handler::err;
+use crafting_interpreters::error_handler::Diagnostic;
+use crafting_interpreters::render::{ErrorFormat, Renderer};
+use crafting_interpreters::{Backend, Lox, RunResult, STACK_SIZE};
 
-// relative modules
-use crate::scanner::Scanner;
-
-pub mod scanner;
-pub mod error_handler;
-pub mod expr;
-pub mod parser;
-pub mod traits;
-pub mod interpreter;
-pub mod stmt;
-pub mod environemnt;
-pub mod loxcallable;
-pub mod resolver;
+struct Cli {
+    rlox : Lox,
+    error_format : ErrorFormat,
+    color : bool,
+    // diagnostics, kept apart from the program's own output
+    stderr : Box<dyn Write>,
+}
 
-pub mod tests;
+impl Cli {
 
-struct Lox {
-    had_error: bool,
-    repl : bool,
-    interpreter : interpreter::Interpreter,
-}
+    fn report (&mut self, source : &str, file : Option<&str>, result : &RunResult) {
+        let renderer = Renderer::new(source, self.color);
+        let mut rendered = String::new();
+        for diagnostic in &result.diagnostics {
+            rendered.push_str(&self.line(renderer.render(diagnostic, self.error_format)));
+        }
+        if let Some(e) = &result.runtime_error {
+            let mut diagnostic = Diagnostic::from(e);
+            diagnostic.file = file.map(str::to_string);
+            rendered.push_str(&self.line(renderer.render(&diagnostic, self.error_format)));
+        }
+        // program output written so far must come out before its errors
+        self.rlox.interpreter().output().flush().unwrap();
+        self.stderr.write_all(rendered.as_bytes()).unwrap();
+    }
 
-impl Lox {
-
-    fn run (&mut self, s : String) {
-
-        // let mut printer = parser::AstPrinter {};
-        let mut s = Scanner::new(s);
-        let tokens = s.scan_tokens();
-
-        if let Ok(tokens) = tokens {
-
-            let mut parser = parser::Parser::new(tokens);
-            match parser.parse() {
-                Ok(stmts) => {
-                    let mut resolver = resolver::Resolver::new(&mut self.interpreter);
-                    
-                    resolver.resolve(&stmts) ;
-                    if resolver.had_error() {return;}
-                    match self.interpreter.interpret(stmts, self.repl) {
-                        Ok(_) => {},
-                        Err(e) => {
-                            match e {
-                                interpreter::Exit::Return(val) => {
-                                    print!("{}", val);
-                                },
-                                interpreter::Exit::RuntimeError(e) => {
-                                    err(e.token.line, &e.message);
-                                
-                            }
-                        }
-                    }
-                    }
-                },
-                Err(_) => {
-                    println!("Error parsing expression");
-                }
-            }
-        } 
+    // json diagnostics are one object per line
+    fn line (&self, mut rendered : String) -> String {
+        if !rendered.ends_with('\n') {
+            rendered.push('\n');
+        }
+        rendered
     }
-    
-    fn run_file(&mut self, file_name: PathBuf) {
-        self.repl = false;
-        let contents = fs::read_to_string(file_name)
+
+    fn run_file (&mut self, file_name : PathBuf) {
+        self.rlox.repl = false;
+        let contents = fs::read_to_string(&file_name)
             .expect("Something went wrong reading the file");
-        self.run(contents);
+        let file_name = file_name.to_string_lossy();
+        let result = self.rlox.run_source(&contents, Some(&file_name));
+        self.report(&contents, Some(&file_name), &result);
+
+        // sysexits codes: 65 for a program that doesn't compile, 70 for one that fails while running
+        if !result.diagnostics.is_empty() {
+            std::process::exit(65);
+        } else if result.runtime_error.is_some() {
+            std::process::exit(70);
+        }
     }
 
-    fn run_prompt(&mut self) {
-        self.repl = true;
-        println!("Running prompt");
-        
+    // * Prompts and echoes go to the interpreter's output, lines come from its input
+    fn run_prompt (&mut self) {
+        self.rlox.repl = true;
+        self.say("Running prompt\n");
+
         let exiting_code = ["exit", "quit", "q"];
-        
+
         loop {
-            print!("> ");
-            std::io::stdout().flush().unwrap();
-            let mut input = String::new();
-            match std::io::stdin().read_line(&mut input) {
-                Ok(_) => {
+            self.say("> ");
+            match self.rlox.interpreter().read_line() {
+                Ok(Some(input)) => {
                     let input = input.trim();
-                    if exiting_code.contains(&input)  || input.len() == 0 {
-                        println!("Exiting");
-                        break;
-                    }
-                    self.run(input.to_string());
-                    if self.had_error {
+                    if exiting_code.contains(&input)  || input.is_empty() {
+                        self.say("Exiting\n");
                         break;
                     }
+                    let result = self.rlox.run(input);
+                    self.report(input, None, &result);
                 },
+                Ok(None) => {
+                    self.say("\nExiting\n");
+                    break;
+                }
                 Err(_) => {
-                    println!("Error reading input");
+                    self.stderr.write_all(b"Error reading input\n").unwrap();
                     break;
                 }
             }
         }
     }
 
+    fn say (&mut self, text : &str) {
+        let output = self.rlox.interpreter().output();
+        output.write_all(text.as_bytes()).unwrap();
+        output.flush().unwrap();
+    }
+}
+
+fn usage () {
+    println!("Usage: rlox [--error-format=human|json] [--backend=tree|vm] [--sandbox] [file_name [args...]]");
+}
+
+// the tree-walker recurses on the native stack, so it gets a deep one
+fn main () {
+    let cli = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run_cli)
+        .expect("Cannot start the interpreter thread");
+    if cli.join().is_err() {
+        std::process::exit(101);
+    }
 }
-fn main() {
 
-    let args : Vec<String> = env::args().collect();
+fn run_cli () {
+
+    let mut error_format = ErrorFormat::Human;
+    let mut backend = Backend::TreeWalk;
+    let mut sandbox = false;
+    let mut args = env::args().skip(1);
+    let mut file = None;
+    // options come first, everything after the script name belongs to the script
+    for arg in args.by_ref() {
+        if let Some(format) = arg.strip_prefix("--error-format=") {
+            match ErrorFormat::parse(format) {
+                Some(f) => error_format = f,
+                None => {
+                    usage();
+                    return;
+                }
+            }
+        } else if let Some(name) = arg.strip_prefix("--backend=") {
+            match Backend::parse(name) {
+                Some(b) => backend = b,
+                None => {
+                    usage();
+                    return;
+                }
+            }
+        } else if arg == "--sandbox" {
+            sandbox = true;
+        } else if arg.starts_with("--") {
+            usage();
+            return;
+        } else {
+            file = Some(arg);
+            break;
+        }
+    }
 
     let p = current_dir().unwrap();
 
     // create a new Lox instance
-
-    let mut rlox = Lox {
-        had_error: false,
-        repl: false,
-        interpreter: interpreter::Interpreter::new(),
+    let mut cli = Cli {
+        rlox : Lox::new(),
+        error_format,
+        color : error_format == ErrorFormat::Human && std::io::stderr().is_terminal(),
+        stderr : Box::new(std::io::stderr()),
     };
-    
-    let n_of_arguments = args.len();
-    if n_of_arguments > 2 {
-        println!("Usage: rlox <file_name>");
-        return;
-    } else if n_of_arguments == 2 {
-        let file_path = p.join(&args[1]);
-        rlox.run_file(file_path);
+
+    cli.rlox.backend = backend;
+    cli.rlox.interpreter().allow_io = !sandbox;
+    cli.rlox.interpreter().script_args = args.collect();
+
+    if let Some(file) = file {
+        let file_path = p.join(file);
+        cli.run_file(file_path);
     } else {
-        rlox.run_prompt();
+        cli.run_prompt();
     }
 }
@@ -0,0 +1,229 @@
+// ? Map values: `{"a": 1, 2: "b"}`. Entries keep insertion order so that
+// ? printing and iterating a map is reproducible from run to run
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use crate::interpreter::Interpreter;
+use crate::loxcallable::{Arity, Callable, NativeMethod, NativeMethodFn};
+use crate::scanner::LiteralType;
+
+#[derive(Debug, Clone)]
+pub enum MapKey {
+    String (Rc<str>),
+    // * bit pattern of the number, with -0 folded into 0
+    Number (u64),
+    Bool (bool),
+    // * instances, classes and functions, compared by identity
+    Object (Callable),
+    // * an instance with a `hash()` method: the key its hash stands for, and
+    // * the instance itself. The hash only picks the bucket; which instance of
+    // * that bucket a key is, the backends settle with `equals()`
+    Hashed (Box<MapKey>, Callable),
+}
+
+impl PartialEq for MapKey {
+    fn eq (&self, other : &MapKey) -> bool {
+        match (self, other) {
+            (MapKey::String(a), MapKey::String(b)) => a == b,
+            (MapKey::Number(a), MapKey::Number(b)) => a == b,
+            (MapKey::Bool(a), MapKey::Bool(b)) => a == b,
+            (MapKey::Object(a), MapKey::Object(b)) => a.same(b),
+            (MapKey::Hashed(a, x), MapKey::Hashed(b, y)) => a == b && x.same(y),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for MapKey {}
+
+impl Hash for MapKey {
+    fn hash<H : Hasher> (&self, state : &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            MapKey::String(s) => s.hash(state),
+            MapKey::Number(bits) => bits.hash(state),
+            MapKey::Bool(b) => b.hash(state),
+            MapKey::Object(object) => object.address().hash(state),
+            MapKey::Hashed(key, _) => key.hash(state),
+        }
+    }
+}
+
+impl MapKey {
+    pub fn from_value (value : &LiteralType) -> Result<MapKey, String> {
+        match value {
+            LiteralType::String(s) => Ok(MapKey::String(Rc::clone(s))),
+            LiteralType::Number(n) => {
+                let n = if *n == 0.0 { 0.0 } else { *n };
+                Ok(MapKey::Number(n.to_bits()))
+            }
+            LiteralType::Bool(b) => Ok(MapKey::Bool(*b)),
+            LiteralType::Callable(object) => Ok(MapKey::Object(object.clone())),
+            _ => Err("Map keys must be strings, numbers, booleans or objects".to_string()),
+        }
+    }
+
+    // * Key of `object`, whose `hash()` method returned `hash`
+    pub fn hashed (object : &Callable, hash : &LiteralType) -> Result<MapKey, String> {
+        match MapKey::from_value(hash) {
+            Ok(MapKey::Object(_)) | Err(_) => Err("'hash()' must return a string, number or boolean".to_string()),
+            Ok(key) => Ok(MapKey::Hashed(Box::new(key), object.clone())),
+        }
+    }
+
+    pub fn to_value (&self) -> LiteralType {
+        match self {
+            MapKey::String(s) => LiteralType::String(Rc::clone(s)),
+            MapKey::Number(bits) => LiteralType::Number(f64::from_bits(*bits)),
+            MapKey::Bool(b) => LiteralType::Bool(*b),
+            MapKey::Object(object) | MapKey::Hashed(_, object) => LiteralType::Callable(object.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LoxMap {
+    entries : Vec<(MapKey, LiteralType)>,
+    index : HashMap<MapKey, usize>,
+    // `Hashed` keys by the key their hash stands for
+    buckets : HashMap<MapKey, Vec<MapKey>>,
+}
+
+impl LoxMap {
+    pub fn new () -> LoxMap {
+        LoxMap::default()
+    }
+
+    pub fn get (&self, key : &MapKey) -> Option<&LiteralType> {
+        self.index.get(key).map(|i| &self.entries[*i].1)
+    }
+
+    pub fn insert (&mut self, key : MapKey, value : LiteralType) {
+        match self.index.get(&key) {
+            Some(i) => self.entries[*i].1 = value,
+            None => {
+                if let MapKey::Hashed(hash, _) = &key {
+                    self.buckets.entry((**hash).clone()).or_default().push(key.clone());
+                }
+                self.index.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+    }
+
+    // * Stored keys with the same hash as `key`
+    pub fn colliding (&self, key : &MapKey) -> Vec<MapKey> {
+        match key {
+            MapKey::Hashed(hash, _) => self.buckets.get(&**hash).cloned().unwrap_or_default(),
+            _ => vec![],
+        }
+    }
+
+    pub fn remove (&mut self, key : &MapKey) -> Option<LiteralType> {
+        let i = self.index.remove(key)?;
+        let (key, value) = self.entries.remove(i);
+        if let MapKey::Hashed(hash, _) = &key {
+            let bucket = self.buckets.get_mut(&**hash).unwrap();
+            bucket.retain(|k| *k != key);
+            if bucket.is_empty() {
+                self.buckets.remove(&**hash);
+            }
+        }
+        // entries after the removed one moved down by one
+        for (k, _) in &self.entries[i..] {
+            *self.index.get_mut(k).unwrap() -= 1;
+        }
+        Some(value)
+    }
+
+    pub fn len (&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty (&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter (&self) -> impl Iterator<Item = &(MapKey, LiteralType)> {
+        self.entries.iter()
+    }
+
+    pub fn keys (&self) -> Vec<LiteralType> {
+        self.entries.iter().map(|(k, _)| k.to_value()).collect()
+    }
+
+    pub fn values (&self) -> Vec<LiteralType> {
+        self.entries.iter().map(|(_, v)| v.clone()).collect()
+    }
+}
+
+// * Looks up `name` on `map`, returning the method bound to it
+pub fn method (map : &Rc<RefCell<LoxMap>>, name : &str) -> Option<NativeMethod> {
+    let (arity, function) : (usize, NativeMethodFn) = match name {
+        "keys" => (0, keys),
+        "values" => (0, values),
+        "has" => (1, has),
+        "remove" => (1, remove),
+        "len" => (0, len),
+        _ => return None,
+    };
+    Some(NativeMethod {
+        name : name.to_string(),
+        receiver : Box::new(LiteralType::Map(Rc::clone(map))),
+        arity : Arity::Exact(arity),
+        function,
+    })
+}
+
+fn entries (receiver : &LiteralType) -> &Rc<RefCell<LoxMap>> {
+    match receiver {
+        LiteralType::Map(m) => m,
+        _ => unreachable!("map method bound to a non-map"),
+    }
+}
+
+fn keys (_interpreter : &mut Interpreter, receiver : &LiteralType, _args : &[LiteralType]) -> Result<LiteralType, String> {
+    Ok(LiteralType::List(Rc::new(RefCell::new(entries(receiver).borrow().keys()))))
+}
+
+fn values (_interpreter : &mut Interpreter, receiver : &LiteralType, _args : &[LiteralType]) -> Result<LiteralType, String> {
+    Ok(LiteralType::List(Rc::new(RefCell::new(entries(receiver).borrow().values()))))
+}
+
+fn has (_interpreter : &mut Interpreter, receiver : &LiteralType, args : &[LiteralType]) -> Result<LiteralType, String> {
+    Ok(has_key(entries(receiver), &MapKey::from_value(&args[0])?))
+}
+
+fn remove (_interpreter : &mut Interpreter, receiver : &LiteralType, args : &[LiteralType]) -> Result<LiteralType, String> {
+    Ok(remove_key(entries(receiver), &MapKey::from_value(&args[0])?))
+}
+
+// ? Map method taking the key its argument stands for
+pub type KeyMethodFn = fn (&Rc<RefCell<LoxMap>>, &MapKey) -> LiteralType;
+
+// * `has` and `remove` by key. The key of an instance with a `hash()` method
+// * takes running Lox code, so the backends work it out at the call site and
+// * call these with it
+pub fn key_method (name : &str) -> Option<KeyMethodFn> {
+    match name {
+        "has" => Some(has_key),
+        "remove" => Some(remove_key),
+        _ => None,
+    }
+}
+
+fn has_key (map : &Rc<RefCell<LoxMap>>, key : &MapKey) -> LiteralType {
+    LiteralType::Bool(map.borrow().get(key).is_some())
+}
+
+// * Removes the entry and returns its value, nil if the key was missing
+fn remove_key (map : &Rc<RefCell<LoxMap>>, key : &MapKey) -> LiteralType {
+    map.borrow_mut().remove(key).unwrap_or(LiteralType::Nil)
+}
+
+fn len (_interpreter : &mut Interpreter, receiver : &LiteralType, _args : &[LiteralType]) -> Result<LiteralType, String> {
+    Ok(LiteralType::Number(entries(receiver).borrow().len() as f64))
+}
@@ -0,0 +1,167 @@
+// ? The `Math` namespace: an instance of the built-in `Math` class whose
+// ? fields hold the math natives and constants (`Math.sqrt(2)`, `Math.PI`)
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::interpreter::Interpreter;
+use crate::loxcallable::{Arity, BuiltinFn, Callable, LoxCLass, LoxInstance, NativeFunction};
+use crate::scanner::LiteralType;
+
+pub fn namespace () -> Rc<RefCell<LoxInstance>> {
+    let class = Rc::new(LoxCLass {
+        name : "Math".to_string(),
+        methods : HashMap::new(),
+        super_class : None,
+    });
+    let math = LoxInstance::new(class);
+
+    let functions : [(&str, Arity, BuiltinFn); 15] = [
+        ("sqrt", Arity::Exact(1), sqrt),
+        ("pow", Arity::Exact(2), pow),
+        ("abs", Arity::Exact(1), abs),
+        ("floor", Arity::Exact(1), floor),
+        ("ceil", Arity::Exact(1), ceil),
+        ("round", Arity::Exact(1), round),
+        ("min", Arity::Variadic(1), min),
+        ("max", Arity::Variadic(1), max),
+        ("sin", Arity::Exact(1), sin),
+        ("cos", Arity::Exact(1), cos),
+        ("tan", Arity::Exact(1), tan),
+        ("log", Arity::Exact(1), log),
+        ("exp", Arity::Exact(1), exp),
+        ("random", Arity::Exact(0), random),
+        ("seed", Arity::Exact(1), seed),
+    ];
+    for (name, arity, function) in functions {
+        let native = NativeFunction::builtin(&format!("Math.{}", name), arity, function);
+        math.borrow_mut().define(name, LiteralType::Callable(Callable::NativeFunction(native)));
+    }
+    math.borrow_mut().define("PI", LiteralType::Number(std::f64::consts::PI));
+    math.borrow_mut().define("E", LiteralType::Number(std::f64::consts::E));
+    math.borrow_mut().freeze();
+    math
+}
+
+// ? splitmix64: small, fast and the same sequence on every platform, so a
+// ? seeded script always draws the same numbers
+#[derive(Debug, Clone)]
+pub struct Random {
+    state : u64,
+}
+
+impl Random {
+    pub fn new (seed : u64) -> Random {
+        Random { state : seed }
+    }
+
+    // * Uniform in [0, 1)
+    pub fn next_f64 (&mut self) -> f64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// * Seeded from the clock, so unseeded scripts draw different numbers on
+// * every run until `Math.seed` pins the sequence
+impl Default for Random {
+    fn default () -> Random {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+        Random::new(now)
+    }
+}
+
+fn number (function : &str, value : &LiteralType) -> Result<f64, String> {
+    match value {
+        LiteralType::Number(n) => Ok(*n),
+        _ => Err(format!("Math.{} expects a number but got {}", function, value.repr())),
+    }
+}
+
+fn unary (function : &str, args : &[LiteralType], op : fn (f64) -> f64) -> Result<LiteralType, String> {
+    Ok(LiteralType::Number(op(number(function, &args[0])?)))
+}
+
+fn binary (function : &str, args : &[LiteralType], op : fn (f64, f64) -> f64) -> Result<LiteralType, String> {
+    Ok(LiteralType::Number(op(number(function, &args[0])?, number(function, &args[1])?)))
+}
+
+// * Folds any number of arguments with `op`
+fn fold (function : &str, args : &[LiteralType], op : fn (f64, f64) -> f64) -> Result<LiteralType, String> {
+    let mut result = number(function, &args[0])?;
+    for arg in &args[1..] {
+        result = op(result, number(function, arg)?);
+    }
+    Ok(LiteralType::Number(result))
+}
+
+fn sqrt (_interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    unary("sqrt", args, f64::sqrt)
+}
+
+fn pow (_interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    binary("pow", args, f64::powf)
+}
+
+fn abs (_interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    unary("abs", args, f64::abs)
+}
+
+fn floor (_interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    unary("floor", args, f64::floor)
+}
+
+fn ceil (_interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    unary("ceil", args, f64::ceil)
+}
+
+// * Halves round away from zero
+fn round (_interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    unary("round", args, f64::round)
+}
+
+fn min (_interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    fold("min", args, f64::min)
+}
+
+fn max (_interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    fold("max", args, f64::max)
+}
+
+fn sin (_interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    unary("sin", args, f64::sin)
+}
+
+fn cos (_interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    unary("cos", args, f64::cos)
+}
+
+fn tan (_interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    unary("tan", args, f64::tan)
+}
+
+// * Natural logarithm
+fn log (_interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    unary("log", args, f64::ln)
+}
+
+fn exp (_interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    unary("exp", args, f64::exp)
+}
+
+fn random (interpreter : &mut Interpreter, _args : &[LiteralType]) -> Result<LiteralType, String> {
+    Ok(LiteralType::Number(interpreter.random.next_f64()))
+}
+
+// * Restarts the `random` sequence; equal seeds give equal sequences
+fn seed (interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    let seed = number("seed", &args[0])?;
+    interpreter.random = Random::new(seed.to_bits());
+    Ok(LiteralType::Nil)
+}
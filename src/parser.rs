@@ -1,23 +1,39 @@
 
 use std::vec;
 
-use crate::expr::{self, Assigment, Binary, Call, Conditional, Expr, Get, Grouping, Literal, Logical, Set, Superr, This, Unary, Variable, Visitor};
-use crate::scanner::{Token, TokenType, LiteralType};
+use crate::expr::{self, Assigment, Binary, Call, Conditional, Expr, Get, Grouping, Index, Lambda, List, Literal, Logical, Map, Set, SetIndex, Superr, This, Unary, Variable, Visitor};
+use crate::scanner::{Span, Token, TokenType, LiteralType};
+use crate::symbol::Symbol;
 use crate::{error_handler::*};
-use crate::stmt::{Block, Breakk, Class, Continuee, Expression, Function, Iff, Print, Returnn, Stmt, Var, Whilee};
+use crate::stmt::{Block, Breakk, Class, Continuee, Expression, ForIn, Function, Iff, Print, Returnn, Stmt, Throw, Tryy, Var, Whilee};
 
 pub struct Parser {
     tokens : Vec<Token>,
     current : usize,
     errors : Vec<ParseError>,
+    // id the next expression node gets
+    next_id : NodeId,
 }
 
-static mut UUID : usize = 0;
-
-pub fn next_uuid () -> usize {
-    unsafe {
-        UUID += 1;
-        return UUID;
+// ? Identity of an expression node, what the resolver's results are keyed by.
+// ? Unique within everything one parser (or a chain of parsers, see
+// ? `Parser::continuing`) produced
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct NodeId (pub usize);
+
+// * Source text of the punctuation tokens `consume` can suggest inserting
+fn punctuation (token : TokenType) -> Option<&'static str> {
+    match token {
+        TokenType::LeftParen => Some("("),
+        TokenType::RightParan => Some(")"),
+        TokenType::LeftBrac => Some("{"),
+        TokenType::RightBrace => Some("}"),
+        TokenType::RightBracket => Some("]"),
+        TokenType::Comma => Some(","),
+        TokenType::Dot => Some("."),
+        TokenType::Colon => Some(":"),
+        TokenType::Semicolon => Some(";"),
+        _ => None,
     }
 }
 
@@ -25,52 +41,75 @@ pub struct AstPrinter {}
 
 impl Visitor<String> for AstPrinter {
 
-    fn visit_superr(&mut self, superr : &expr::Superr) -> String {
+    fn visit_superr(&mut self, _superr : &expr::Superr) -> String {
         todo!()
     }
 
-    fn visit_call(&mut self, call : &Call) -> String {
+    fn visit_call(&mut self, _call : &Call) -> String {
         todo!()
     }
 
-    fn visit_this(&mut self, this : &This) -> String {
+    fn visit_this(&mut self, _this : &This) -> String {
         todo!()
     }
 
     fn visit_binary(&mut self, binary : &crate::expr::Binary) -> String {
-        return self.parenthesize(&binary.operator.lexeme, vec![&binary.left, &binary.right]);
+        self.parenthesize(&binary.operator.lexeme, vec![&binary.left, &binary.right])
     }
     fn visit_grouping(&mut self, grouping : &crate::expr::Grouping) -> String {
-        return self.parenthesize(&"group".to_string(), vec![&grouping.expression]);
+        self.parenthesize("group", vec![&grouping.expression])
     }
 
     fn visit_literal(&mut self, literal : &crate::expr::Literal) -> String {
-        return literal.value.to_string();
+        literal.value.to_string()
     }
 
     fn visit_unary(&mut self, unary : &crate::expr::Unary) -> String {
-        return self.parenthesize(&unary.operator.lexeme, vec![&unary.right]);
+        self.parenthesize(&unary.operator.lexeme, vec![&unary.right])
     }
 
     fn visit_conditional(&mut self, conditional : &Conditional) -> String {
-        return self.parenthesize(&"?:".to_string(), vec![&conditional.condition, &conditional.then_branch, &conditional.else_branch]);
+        self.parenthesize("?:", vec![&conditional.condition, &conditional.then_branch, &conditional.else_branch])
     }
-    fn visit_variable(&mut self, variable : &crate::expr::Variable) -> String {
+    fn visit_variable(&mut self, _variable : &crate::expr::Variable) -> String {
         todo!()
     }
 
-    fn visit_assigment(&mut self, assigment : &crate::expr::Assigment) -> String {
+    fn visit_assigment(&mut self, _assigment : &crate::expr::Assigment) -> String {
         todo!()
     }
-    fn visit_logical(&mut self, logical : &Logical) -> String {
+    fn visit_logical(&mut self, _logical : &Logical) -> String {
         todo!()
     }
-    fn visit_get(&mut self, get : &Get) -> String {
+    fn visit_get(&mut self, _get : &Get) -> String {
         todo!()
     }
-    fn visit_set(&mut self, set : &Set) -> String {
+    fn visit_set(&mut self, _set : &Set) -> String {
         todo!()
     }
+    fn visit_list(&mut self, list : &List) -> String {
+        self.parenthesize("list", list.elements.iter().collect())
+    }
+    fn visit_index(&mut self, index : &Index) -> String {
+        self.parenthesize("[]", vec![&index.object, &index.index])
+    }
+    fn visit_setindex(&mut self, setindex : &SetIndex) -> String {
+        self.parenthesize("[]=", vec![&setindex.object, &setindex.index, &setindex.value])
+    }
+    fn visit_map(&mut self, map : &Map) -> String {
+        // keys and values alternate
+        let entries = map.keys.iter().zip(map.values.iter()).flat_map(|(k, v)| [k, v]).collect();
+        self.parenthesize("map", entries)
+    }
+    fn visit_lambda(&mut self, lambda : &Lambda) -> String {
+        let params = lambda.declaration.params.iter().map(|p| p.lexeme.to_string()).collect::<Vec<String>>();
+        // statements aren't printed, but an arrow body is just a returned expression
+        let body = match lambda.declaration.body.as_slice() {
+            [Stmt::Returnn(Returnn { value : Some(value), .. })] => vec![value],
+            _ => vec![],
+        };
+        self.parenthesize(&format!("fun ({})", params.join(" ")), body)
+    }
 }
 
 impl AstPrinter {
@@ -78,28 +117,48 @@ impl AstPrinter {
         println!("{}", expr.accept(self));
     }
 
-    fn parenthesize (&mut self, name : &String, exprs : Vec<&Expr>) -> String {
+    fn parenthesize (&mut self, name : &str, exprs : Vec<&Expr>) -> String {
         let mut s = String::new();
-        s.push_str("(");
+        s.push('(');
         s.push_str(name);
         for expr in exprs {
             s.push_str(format!(" {}", expr.accept(self)).as_str());
         }
-        s.push_str(")");
-        return s;
+        s.push(')');
+        s
     }
 }
 
 impl Parser {
     pub fn new (tokens : Vec<Token>) -> Parser {
+        Parser::continuing(tokens, NodeId::default())
+    }
+
+    // * Parser whose node ids start at `next_id`, so trees parsed one after
+    // * another (REPL lines) never share an id
+    pub fn continuing (tokens : Vec<Token>, next_id : NodeId) -> Parser {
         Parser {
             tokens,
             current: 0,
             errors: vec![],
+            next_id,
         }
     }
 
-    pub fn parse (&mut self) -> Result<Vec<Stmt>, ParseError>{
+    // * The first id this parser has not handed out
+    pub fn next_id (&self) -> NodeId {
+        self.next_id
+    }
+
+    fn new_id (&mut self) -> NodeId {
+        let id = self.next_id;
+        self.next_id.0 += 1;
+        id
+    }
+
+    // * Parses the whole program, recovering after each error so that
+    // * every syntax error is reported in one go
+    pub fn parse (&mut self) -> Result<Vec<Stmt>, Vec<ParseError>>{
         let mut statements = vec![];
 
         while !self.is_at_end() {
@@ -109,13 +168,18 @@ impl Parser {
             }
         }
 
+        if !self.errors.is_empty() {
+            return Err(std::mem::take(&mut self.errors));
+        }
         Ok(statements)
     }
 
     fn declaration (&mut self) -> Option<Stmt> {
         let stmt = if self.match_token(&[TokenType::Var]) {
             self.var_declaration()
-        } else if self.match_token(&[TokenType::Fun]) {
+        } else if self.check(TokenType::Fun) && self.peek_next().token_type != TokenType::LeftParen {
+            // `fun (` starts an anonymous function expression instead
+            self.advance();
             self.func_delaration("function")
         } else if self.match_token(&[TokenType::Class]) {
             self.class_declation ()
@@ -133,15 +197,31 @@ impl Parser {
     }
 
     fn func_delaration (&mut self, kind : &str) -> Result<Stmt, ParseError> {
+        let start = self.previous().span;
         let name = self.consume(TokenType::Identifier, format!("Expect {} name", kind).as_str())?;
         self.consume(TokenType::LeftParen, format!("Expect '(' after {} name", kind).as_str())?;
+        let params = self.parameters()?;
+        self.consume(TokenType::LeftBrac, "Expect '{' before function body")?;
+
+        let body = self.block()?;
+
+        Ok(Stmt::Function(Function {
+            name,
+            params,
+            body,
+            span : self.span_from(start)
+        }))   
+    }
 
+    // * Parameter names up to and including the closing ')'
+    fn parameters (&mut self) -> Result<Vec<Token>, ParseError> {
         let mut params = vec![];
 
         if !self.check(TokenType::RightParan) {
             loop {
                 if params.len() >= 255 {
-                    parse_error(&self.peek(), "Cannot have more than 255 parameters");
+                    let e = parse_error(&self.peek(), "Cannot have more than 255 parameters");
+                    self.errors.push(e);
                 }
                 
                 params.push(self.consume(TokenType::Identifier, "Expect parameter name")?);
@@ -150,25 +230,89 @@ impl Parser {
         }
 
         self.consume(TokenType::RightParan, "Expect ')' after parameters")?;
-        self.consume(TokenType::LeftBrac, "Expect '{' before function body")?;
+        Ok(params)
+    }
 
+    // fun (a, b) { ... }
+    fn lambda (&mut self) -> Result<Expr, ParseError> {
+        let keyword = self.advance();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'fun'")?;
+        let params = self.parameters()?;
+        self.consume(TokenType::LeftBrac, "Expect '{' before function body")?;
         let body = self.block()?;
+        Ok(self.make_lambda(keyword.span, params, body))
+    }
 
-        Ok(Stmt::Function(Function {
-            name : name,
-            params : params,
-            body : body
-        }))   
+    // (a, b) => a + b  or  (a, b) => { ... }
+    fn arrow_lambda (&mut self) -> Result<Expr, ParseError> {
+        let start = self.advance().span;
+        let params = self.parameters()?;
+        let arrow = self.consume(TokenType::Arrow, "Expect '=>' after parameters")?;
+        let body = if self.match_token(&[TokenType::LeftBrac]) {
+            self.block()?
+        } else {
+            let value = self.second_level()?;
+            let span = value.span();
+            vec![Stmt::Returnn(Returnn {
+                keyword : arrow,
+                value : Some(value),
+                span
+            })]
+        };
+        Ok(self.make_lambda(start, params, body))
+    }
+
+    fn make_lambda (&mut self, start : Span, params : Vec<Token>, body : Vec<Stmt>) -> Expr {
+        let span = self.span_from(start);
+        Expr::Lambda(Lambda {
+            declaration : Function {
+                // anonymous functions show up as "lambda" in traces and when printed
+                name : Token {
+                    token_type : TokenType::Identifier,
+                    lexeme : Symbol::intern("lambda"),
+                    literal : LiteralType::Nil,
+                    span : start,
+                },
+                params,
+                body,
+                span
+            },
+            span,
+            id : self.new_id()
+        })
+    }
+
+    // * Whether the '(' at the current token opens an arrow function's parameter list
+    fn is_arrow_lambda (&self) -> bool {
+        let mut i = self.current + 1;
+        if self.tokens[i].token_type != TokenType::RightParan {
+            loop {
+                if self.tokens[i].token_type != TokenType::Identifier {
+                    return false;
+                }
+                i += 1;
+                if self.tokens[i].token_type != TokenType::Comma {
+                    break;
+                }
+                i += 1;
+            }
+            if self.tokens[i].token_type != TokenType::RightParan {
+                return false;
+            }
+        }
+        self.tokens[i + 1].token_type == TokenType::Arrow
     }
 
     fn class_declation (&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous().span;
         let name = self.consume(TokenType::Identifier, "Expected an identifier")?;
 
         let sup_class = if self.match_token(&[TokenType::Less]) {
             self.consume(TokenType::Identifier, "Expect super class name")?;
             Some(Expr::Variable(Variable {
                 name : self.previous(),
-                uuid : next_uuid()
+                span : self.previous().span,
+                id : self.new_id()
             }))
         } else {
             None
@@ -196,14 +340,16 @@ impl Parser {
         self.consume(TokenType::RightBrace, "Expected '}' after function declaration")?;
 
         Ok(Stmt::Class(Class {
-            name : name,
-            methods : methods,
-            SuperClass : sup_class
+            name,
+            methods,
+            super_class : sup_class,
+            span : self.span_from(start)
         }))
     }
 
 
     fn var_declaration (&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous().span;
         let token = self.consume(TokenType::Identifier, "Expect a variable name")?;
 
         let mut initializer= None;
@@ -214,7 +360,8 @@ impl Parser {
         Ok (
             Stmt::Var(Var {
                 name : token,
-                initializer :  initializer
+                initializer,
+                span : self.span_from(start)
             })
         )
     }
@@ -223,14 +370,15 @@ impl Parser {
         match self.peek().token_type {
             TokenType::Print => {
                 self.advance(); 
-                return self.print_statement()
+                self.print_statement()
             }
             TokenType::LeftBrac => {
-                self.advance();
+                let start = self.advance().span;
                 let block = self.block()?;
-                return Ok(Stmt::Block(Block {
-                    statements : block
-                }));
+                Ok(Stmt::Block(Block {
+                    statements : block,
+                    span : self.span_from(start)
+                }))
             }
             TokenType::If => {
                 self.advance();
@@ -238,11 +386,14 @@ impl Parser {
             }
             TokenType::While => {
                 self.advance();
-                self.while_statement()
+                self.while_statement(None)
             }
             TokenType::For => {
                 self.advance();
-                self.for_statement()
+                self.for_statement(None)
+            }
+            TokenType::Identifier if self.peek_next().token_type == TokenType::Colon => {
+                self.labeled_statement()
             }
             TokenType::Break => {
                 self.advance();
@@ -256,15 +407,25 @@ impl Parser {
                 self.advance();
                 self.return_statement()
             }
+            TokenType::Throw => {
+                self.advance();
+                self.throw_statement()
+            }
+            TokenType::Try => {
+                self.advance();
+                self.try_statement()
+            }
             _ => {self.expression_statement()}
         }
     }
 
     fn print_statement (&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous().span;
         let value = self.expression()?;
         self.consume(TokenType::Semicolon, "Expect ';' after value")?;
         Ok(Stmt::Print(Print {
-            expression : Box::new(value)
+            expression : Box::new(value),
+            span : self.span_from(start)
         }))
     }
 
@@ -276,9 +437,61 @@ impl Parser {
             None
         };
         self.consume(TokenType::Semicolon, "Expect ';' after return value")?;
+        let span = self.span_from(keyword.span);
         Ok(Stmt::Returnn(Returnn {
-            keyword : keyword,
-            value : value
+            keyword,
+            value,
+            span
+        }))
+    }
+
+    fn throw_statement (&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous();
+        let value = self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after thrown value")?;
+        let span = self.span_from(keyword.span);
+        Ok(Stmt::Throw(Throw {
+            keyword,
+            value : Box::new(value),
+            span
+        }))
+    }
+
+    // * "try" <block> ( "catch" "(" <identifier> ")" <block> )? ( "finally" <block> )?
+    fn try_statement (&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous();
+        self.consume(TokenType::LeftBrac, "Expect '{' after 'try'")?;
+        let body = self.block()?;
+
+        let mut catch_name = None;
+        let mut catch_body = None;
+        if self.match_token(&[TokenType::Catch]) {
+            self.consume(TokenType::LeftParen, "Expect '(' after 'catch'")?;
+            catch_name = Some(self.consume(TokenType::Identifier, "Expect exception variable name")?);
+            self.consume(TokenType::RightParan, "Expect ')' after exception variable")?;
+            self.consume(TokenType::LeftBrac, "Expect '{' before catch body")?;
+            catch_body = Some(self.block()?);
+        }
+
+        let finally_body = if self.match_token(&[TokenType::Finally]) {
+            self.consume(TokenType::LeftBrac, "Expect '{' after 'finally'")?;
+            Some(self.block()?)
+        } else {
+            None
+        };
+
+        if catch_body.is_none() && finally_body.is_none() {
+            return Err(parse_error(&self.peek(), "Expect 'catch' or 'finally' after try block"));
+        }
+
+        let span = self.span_from(keyword.span);
+        Ok(Stmt::Tryy(Tryy {
+            keyword,
+            body,
+            catch_name,
+            catch_body,
+            finally_body,
+            span
         }))
     }
 
@@ -292,10 +505,11 @@ impl Parser {
             }
         }
         self.consume(TokenType::RightBrace, "Expect '}' after block")?;
-        return Ok(statements);
+        Ok(statements)
     }
 
     fn if_statement (&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous().span;
         self.consume(TokenType::LeftParen, "Expect '(' after 'if'")?;
         let condition = self.expression()?;
 
@@ -307,17 +521,33 @@ impl Parser {
             return Ok(Stmt::Iff(Iff {
                 condition : Box::new(condition),
                 then_branch : Box::new(then_branch),
-                else_branch : Some(Box::new(else_branch))
+                else_branch : Some(Box::new(else_branch)),
+                span : self.span_from(start)
             }))
         }
         Ok(Stmt::Iff(Iff {
             condition : Box::new(condition),
             then_branch : Box::new(then_branch),
-            else_branch : None
+            else_branch : None,
+            span : self.span_from(start)
         }))
     }
 
-    fn while_statement (&mut self) -> Result<Stmt, ParseError> {
+    // outer: while (...) ...  — a label `break`/`continue` can name
+    fn labeled_statement (&mut self) -> Result<Stmt, ParseError> {
+        let label = self.advance();
+        self.advance();
+        if self.match_token(&[TokenType::While]) {
+            self.while_statement(Some(label))
+        } else if self.match_token(&[TokenType::For]) {
+            self.for_statement(Some(label))
+        } else {
+            Err(parse_error(&self.peek(), "Expect loop after label"))
+        }
+    }
+
+    fn while_statement (&mut self, label : Option<Token>) -> Result<Stmt, ParseError> {
+        let start = label.as_ref().map_or(self.previous().span, |l| l.span);
         self.consume(TokenType::LeftParen, "Expect '(' after 'while'")?;
 
         let condition = self.expression()?;
@@ -326,16 +556,22 @@ impl Parser {
         let body = self.statement()?;
 
         Ok (Stmt::Whilee(Whilee {
+            label,
             condition : Box::new(condition),
             body : Box::new(body),
-            is_for : false
+            increment : None,
+            span : self.span_from(start)
         }))
     }
 
-    fn for_statement (&mut self) -> Result<Stmt, ParseError> {
-        
+    fn for_statement (&mut self, label : Option<Token>) -> Result<Stmt, ParseError> {
+        let start = label.as_ref().map_or(self.previous().span, |l| l.span);
         self.consume(TokenType::LeftParen, "Expect '(' after 'for'")?;
 
+        if self.check(TokenType::Identifier) && self.peek_next().token_type == TokenType::In {
+            return self.for_in_statement(label, start);
+        }
+
         let initializer = if self.match_token(&[TokenType::Semicolon]) {
             None
         } else if self.match_token(&[TokenType::Var]) {
@@ -349,7 +585,8 @@ impl Parser {
         } else {
             expr::Expr::Literal(Literal {
                 value : LiteralType::Bool(true),
-                uuid : next_uuid()
+                span : self.peek().span,
+                id : self.new_id()
             })
         };
         self.consume(TokenType::Semicolon, "Expect ';' after loop condition")?;
@@ -361,52 +598,78 @@ impl Parser {
         };
         self.consume(TokenType::RightParan, "Expect ')' after for clauses")?;
 
-        let mut body = self.statement()?;
+        let body = self.statement()?;
         
-        // * Desugaring for loop
-        if let Some(increment) = increment {
-            body = Stmt::Block(Block {
-                statements : vec![body, Stmt::Expression(Expression {
-                    expression : Box::new(increment)
-                })]
-            })
-        }
-        // * constructing the while loop
+        // * Desugaring for loop, the increment stays separate from the
+        // * body so that `continue` still runs it
 
-        body = Stmt::Whilee(Whilee {
+        let mut body = Stmt::Whilee(Whilee {
+            label,
             condition : Box::new(condition),
             body : Box::new(body),
-            is_for : true
+            increment,
+            span : self.span_from(start)
         });
 
         if let Some(initializer) = initializer {
             body = Stmt::Block(Block {
-                statements : vec![initializer, body]
+                statements : vec![initializer, body],
+                span : self.span_from(start)
             })
         }
 
-        return Ok(body);
+        Ok(body)
+    }
+
+    // for (name in iterable) body
+    fn for_in_statement (&mut self, label : Option<Token>, start : Span) -> Result<Stmt, ParseError> {
+        let name = self.advance();
+        let keyword = self.advance();
+        let iterable = self.expression()?;
+        self.consume(TokenType::RightParan, "Expect ')' after for-in iterable")?;
+
+        let body = self.statement()?;
+        Ok(Stmt::ForIn(ForIn {
+            label,
+            name,
+            keyword,
+            iterable : Box::new(iterable),
+            body : Box::new(body),
+            span : self.span_from(start)
+        }))
     }
 
     fn expression_statement (&mut self) -> Result<Stmt, ParseError> {
+        let start = self.peek().span;
         let value = self.expression()?;
         self.consume(TokenType::Semicolon, "Expect ';' after value")?;
         Ok(Stmt::Expression(Expression {
-            expression : Box::new(value)
+            expression : Box::new(value),
+            span : self.span_from(start)
         }))
     }
 
     fn break_statement (&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous();
+        let label = if self.check(TokenType::Identifier) { Some(self.advance()) } else { None };
         self.consume(TokenType::Semicolon, "Expect ';' after break")?;
+        let span = self.span_from(keyword.span);
         Ok(Stmt::Breakk(Breakk {
-            keyword : self.previous()
+            keyword,
+            label,
+            span
         }))
     }
 
     fn continue_statement (&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous();
+        let label = if self.check(TokenType::Identifier) { Some(self.advance()) } else { None };
         self.consume(TokenType::Semicolon, "Expect ';' after continue")?;
+        let span = self.span_from(keyword.span);
         Ok(Stmt::Continuee(Continuee {
-            keyword : self.previous()
+            keyword,
+            label,
+            span
         }))
     }
 
@@ -422,11 +685,13 @@ impl Parser {
         while self.match_token(&[TokenType::Comma]) {
             let operator = self.previous();
             let right = self.second_level()?;
+            let span = expr.span().to(right.span());
             expr = Expr::Binary(Binary {
                 left : Box::new(expr),
-                operator : operator,
+                operator,
                 right : Box::new(right),
-                uuid : next_uuid()
+                span,
+                id : self.new_id()
             });
         }
         Ok(expr)
@@ -441,11 +706,13 @@ impl Parser {
                 let then_branch = self.second_level()?;
                 self.consume(TokenType::Colon, "Expect ':' after then branch")?;
                 let else_branch = self.second_level()?;
+                let span = condition.span().to(else_branch.span());
                 return Ok(Expr::Conditional(Conditional {
                     condition : Box::new(condition),
                     then_branch : Box::new(then_branch),
                     else_branch : Box::new(else_branch),
-                    uuid : next_uuid()
+                    span,
+                    id : self.new_id()
                 }));
             }
             TokenType::Equal => {
@@ -453,13 +720,15 @@ impl Parser {
                 self.advance();
                 let eq = self.previous();
                 let value = self.second_level()?;
+                let span = condition.span().to(value.span());
                 match condition {
                     Expr::Variable(v) => {
                         let name = v.name;
                         return Ok(Expr :: Assigment(Assigment {
-                            name : name,
+                            name,
                             value : Box::new(value),
-                            uuid : next_uuid()
+                            span,
+                            id : self.new_id()
                         }))
                     },
                     Expr::Get(g) => {
@@ -467,7 +736,18 @@ impl Parser {
                             object : g.object,
                             name : g.name,
                             value : Box::new(value),
-                            uuid : next_uuid()
+                            span,
+                            id : self.new_id()
+                        }))
+                    }
+                    Expr::Index(i) => {
+                        return Ok (Expr::SetIndex(SetIndex {
+                            object : i.object,
+                            bracket : i.bracket,
+                            index : i.index,
+                            value : Box::new(value),
+                            span,
+                            id : self.new_id()
                         }))
                     }
                     _ => {
@@ -488,14 +768,16 @@ impl Parser {
         while self.match_token(&[TokenType::Or]) {
             let operator = self.previous();
             let right = self.logical_and()?;
+            let span = expr.span().to(right.span());
             expr = Expr::Logical(Logical {
                 left : Box::new(expr),
-                operator : operator,
+                operator,
                 right : Box::new(right),
-                uuid : next_uuid()
+                span,
+                id : self.new_id()
             })
         }
-        return Ok(expr);
+        Ok(expr)
     }
     fn logical_and (&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.equality()?;
@@ -503,11 +785,13 @@ impl Parser {
         while self.match_token(&[TokenType::And]) {
             let operator = self.previous();
             let right = self.equality()?;
+            let span = expr.span().to(right.span());
             expr = Expr::Logical(Logical {
                 left : Box::new(expr),
-                operator : operator,
+                operator,
                 right : Box::new(right),
-                uuid : next_uuid()
+                span,
+                id : self.new_id()
             })
         }
 
@@ -520,14 +804,16 @@ impl Parser {
         while self.match_token(&[TokenType::EqualEqual, TokenType::BangEqual]) {
             let operator = self.previous ();
             let right = self.comparison()?;
+            let span = expr.span().to(right.span());
             expr = Expr::Binary(Binary {
                 left : Box::new(expr),
-                operator : operator, 
+                operator, 
                 right : Box::new(right),
-                uuid : next_uuid()
+                span,
+                id : self.new_id()
             });
         }
-        return Ok(expr);
+        Ok(expr)
     }
 
     fn comparison (&mut self) -> Result<Expr, ParseError> {
@@ -537,15 +823,17 @@ impl Parser {
             let operator = self.previous();
             let right = self.term()?;
 
+            let span = expr.span().to(right.span());
             expr = Expr::Binary(Binary {
                 left : Box::new(expr), 
-                operator : operator,
+                operator,
                 right : Box::new(right),
-                uuid : next_uuid()
+                span,
+                id : self.new_id()
             })
         }
 
-        return Ok(expr);
+        Ok(expr)
     }
 
     fn term (&mut self) -> Result<Expr, ParseError> {
@@ -554,14 +842,16 @@ impl Parser {
         while self.match_token(&[TokenType::Minus, TokenType::Plus]) {
             let operator = self.previous();
             let right = self.factor()?;
+            let span = expr.span().to(right.span());
             expr = Expr::Binary (Binary {
                 left : Box::new(expr),
-                operator : operator,
+                operator,
                 right : Box::new(right),
-                uuid : next_uuid()
+                span,
+                id : self.new_id()
             })
         }
-        return Ok(expr);
+        Ok(expr)
     }
 
     fn factor (&mut self) -> Result<Expr, ParseError> {
@@ -570,25 +860,29 @@ impl Parser {
         while self.match_token(&[TokenType::Slash, TokenType::Star, TokenType::Percentage]) {
             let operator = self.previous ();
             let right = self.unary ()?;
+            let span = expr.span().to(right.span());
             expr = Expr::Binary (Binary {
                 left : Box::new(expr),
-                operator : operator,
+                operator,
                 right : Box::new(right),
-                uuid : next_uuid()
+                span,
+                id : self.new_id()
             })
         }
 
-        return Ok(expr);
+        Ok(expr)
     }
 
     fn unary (&mut self) -> Result<Expr, ParseError> {
         if self.match_token(&[TokenType::Bang, TokenType::Minus]) {
             let operator = self.previous ();
             let right = self.unary()?;
+            let span = operator.span.to(right.span());
             return Ok(Expr::Unary(Unary {
-                operator : operator,
+                operator,
                 right : Box::new(right),
-                uuid : next_uuid()
+                span,
+                id : self.new_id()
             }))
         }
 
@@ -603,17 +897,31 @@ impl Parser {
                 expr = self.finish_call(expr)?;
             } else if self.match_token(&[TokenType::Dot]) {
                 let name = self.consume(TokenType::Identifier, "Expect property name after '.'")?;
+                let span = expr.span().to(name.span);
                 expr = Expr::Get(Get {
                     object : Box::new(expr),
-                    name : name,
-                    uuid : next_uuid()
+                    name,
+                    span,
+                    id : self.new_id()
                 });
             
+            } else if self.match_token(&[TokenType::LeftBracket]) {
+                let bracket = self.previous();
+                let index = self.expression()?;
+                self.consume(TokenType::RightBracket, "Expect ']' after index")?;
+                let span = self.span_from(expr.span());
+                expr = Expr::Index(Index {
+                    object : Box::new(expr),
+                    bracket,
+                    index : Box::new(index),
+                    span,
+                    id : self.new_id()
+                });
             } else {
                 break;
             }
         }
-        return Ok(expr);
+        Ok(expr)
     }
 
     fn primary (&mut self) -> Result<Expr, ParseError> {
@@ -622,60 +930,112 @@ impl Parser {
                 self.advance();
                 Ok(Expr::Literal(Literal {
                     value : LiteralType::Bool(false),
-                    uuid : next_uuid()
+                    span : self.previous().span,
+                    id : self.new_id()
                 }))
             }
             TokenType::True => {
                 self.advance();
                 Ok(Expr::Literal(Literal {
                     value : LiteralType::Bool(true),
-                    uuid : next_uuid()
+                    span : self.previous().span,
+                    id : self.new_id()
                 }))
             }
             TokenType::Nil => {
                 self.advance();
                 Ok(Expr::Literal(Literal {
                     value : LiteralType::Nil,
-                    uuid : next_uuid()
+                    span : self.previous().span,
+                    id : self.new_id()
                 }))
             }
             TokenType::Number => {
                 self.advance();
                 Ok(Expr::Literal(Literal {
                     value : self.previous().literal.clone(),
-                    uuid : next_uuid()
+                    span : self.previous().span,
+                    id : self.new_id()
                 }))
             }
             TokenType::String => {
                 self.advance();
                 Ok(Expr::Literal(Literal {
-                    value : LiteralType::String(self.previous().literal.to_string()),
-                    uuid : next_uuid()
+                    value : LiteralType::String(self.previous().literal.to_string().into()),
+                    span : self.previous().span,
+                    id : self.new_id()
                 }))
             }
+            TokenType::LeftParen if self.is_arrow_lambda() => self.arrow_lambda(),
+            TokenType::Fun => self.lambda(),
             TokenType::LeftParen => {
-                self.advance();
+                let start = self.advance().span;
                 let expr = self.expression()?;
 
                 self.consume(TokenType::RightParan, "Expect ')' after expression")?;
 
                 Ok(Expr::Grouping(Grouping {
                     expression : Box::new(expr),
-                    uuid : next_uuid()
+                    span : self.span_from(start),
+                    id : self.new_id()
+                }))
+            }
+            TokenType::LeftBracket => {
+                let bracket = self.advance();
+                let mut elements = vec![];
+                if !self.check(TokenType::RightBracket) {
+                    loop {
+                        elements.push(self.second_level()?);
+                        if !self.match_token(&[TokenType::Comma]) {break;}
+                    }
+                }
+                self.consume(TokenType::RightBracket, "Expect ']' after list elements")?;
+                let span = self.span_from(bracket.span);
+                Ok(Expr::List(List {
+                    bracket,
+                    elements,
+                    span,
+                    id : self.new_id()
+                }))
+            }
+            // a statement starting with '{' is a block, so a brace only
+            // reaches here in expression position
+            TokenType::LeftBrac => {
+                let brace = self.advance();
+                let mut keys = vec![];
+                let mut values = vec![];
+                if !self.check(TokenType::RightBrace) {
+                    loop {
+                        keys.push(self.second_level()?);
+                        self.consume(TokenType::Colon, "Expect ':' after map key")?;
+                        values.push(self.second_level()?);
+                        if !self.match_token(&[TokenType::Comma]) {break;}
+                    }
+                }
+                self.consume(TokenType::RightBrace, "Expect '}' after map entries")?;
+                let span = self.span_from(brace.span);
+                Ok(Expr::Map(Map {
+                    brace,
+                    keys,
+                    values,
+                    span,
+                    id : self.new_id()
                 }))
             }
             TokenType::Identifier => {
                 self.advance();
                 Ok (Expr::Variable(Variable {
                     name : self.previous(),
-                    uuid : next_uuid()
+                    span : self.previous().span,
+                    id : self.new_id()
                 }))
             }
             TokenType::This => {
                 self.advance();
                 Ok(Expr::This(This {
                     keyword : self.previous(),
-                    uuid : next_uuid()
+                    span : self.previous().span,
+                    id : self.new_id()
                 }))
             },
             TokenType::Super => {
@@ -683,10 +1043,12 @@ impl Parser {
                 let keyword = self.previous();
                 self.consume(TokenType::Dot, "Expect '.' after 'super'")?;
                 let method = self.consume(TokenType::Identifier, "Expect superclass method name")?;
+                let span = keyword.span.to(method.span);
                 Ok (Expr::Superr(Superr {
-                    keyword : keyword,
-                    method : method,
-                    uuid : next_uuid()
+                    keyword,
+                    method,
+                    span,
+                    id : self.new_id()
                 }))
             }
             _ => {
@@ -701,7 +1063,9 @@ impl Parser {
         let curr = self.peek();
 
         // parsing error
-        Err(parse_error(&curr, msg))
+        let mut e = parse_error(&curr, msg);
+        e.help = punctuation(token).map(|p| format!("insert '{}' here", p));
+        Err(e)
     }
 
     fn synchronise (&mut self) {
@@ -712,7 +1076,7 @@ impl Parser {
 
             match self.peek().token_type {
                 // get all statement tokens
-                TokenType::Class | TokenType::Fun | TokenType::Var | TokenType::For | TokenType::If | TokenType::While | TokenType::Print | TokenType::Return => return,
+                TokenType::Class | TokenType::Fun | TokenType::Var | TokenType::For | TokenType::If | TokenType::While | TokenType::Print | TokenType::Return | TokenType::Throw | TokenType::Try => return,
                 _ => {}
             }
             self.advance();
@@ -726,13 +1090,13 @@ impl Parser {
                 return true;
             }
         }
-        return false;
+        false
     }
 
     fn check (&mut self, token : TokenType) -> bool{
         if self.is_at_end() {return false;}
 
-        return self.peek().token_type == token;
+        self.peek().token_type == token
     }
 
     fn advance (&mut self) -> Token {
@@ -741,15 +1105,26 @@ impl Parser {
         self.previous()
     }
 
+    // * Span from `start` up to (and including) the last consumed token
+    fn span_from (&self, start : Span) -> Span {
+        start.to(self.tokens[self.current - 1].span)
+    }
+
     fn previous (&mut self) -> Token {
         self.tokens[self.current - 1].clone() 
     }
 
     fn peek (&mut self) -> Token {
-        return self.tokens[self.current].clone();
+        self.tokens[self.current].clone()
     }
+
+    fn peek_next (&mut self) -> Token {
+        let next = (self.current + 1).min(self.tokens.len() - 1);
+        self.tokens[next].clone()
+    }
+
     fn is_at_end (&mut self) -> bool {
-        return self.peek().token_type == TokenType::EOF;
+        self.peek().token_type == TokenType::EOF
     }
 
     fn finish_call (&mut self, callee : Expr) -> Result<Expr, ParseError> {
@@ -758,7 +1133,8 @@ impl Parser {
             // collect all arguments
             loop {
                 if args.len() >= 255 {
-                    parse_error(&self.peek(), "Cannot have more than 255 arguments");
+                    let e = parse_error(&self.peek(), "Cannot have more than 255 arguments");
+                    self.errors.push(e);
                 }
                 args.push(self.second_level()?);
                 if !self.match_token(&[TokenType::Comma]) {break;}
@@ -767,11 +1143,13 @@ impl Parser {
         }
 
         let paren = self.consume(TokenType::RightParan, "Expect ')' after arguments")?;
+        let span = callee.span().to(paren.span);
         Ok(Expr::Call(Call {
             callee : Box::new(callee),
-            paren : paren,
+            paren,
             arguments : args,
-            uuid : next_uuid()
+            span,
+            id : self.new_id()
         }))
     }
     
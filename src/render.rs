@@ -0,0 +1,164 @@
+// ? Turns diagnostics into text for humans (rustc-style, with the offending
+// ? source line underlined) or for tools (one JSON object per line)
+
+use crate::error_handler::{Diagnostic, Severity};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    Human,
+    Json,
+}
+
+impl ErrorFormat {
+    pub fn parse (s : &str) -> Option<ErrorFormat> {
+        match s {
+            "human" => Some(ErrorFormat::Human),
+            "json" => Some(ErrorFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+const RED : &str = "\x1b[1;31m";
+const YELLOW : &str = "\x1b[1;33m";
+const BLUE : &str = "\x1b[1;34m";
+const CYAN : &str = "\x1b[1;36m";
+const BOLD : &str = "\x1b[1m";
+const RESET : &str = "\x1b[0m";
+
+pub struct Renderer<'a> {
+    source : &'a str,
+    color : bool,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new (source : &'a str, color : bool) -> Renderer<'a> {
+        Renderer {
+            source,
+            color,
+        }
+    }
+
+    fn paint (&self, style : &str, text : &str) -> String {
+        if self.color {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+
+    pub fn render (&self, diagnostic : &Diagnostic, format : ErrorFormat) -> String {
+        match format {
+            ErrorFormat::Human => self.human(diagnostic),
+            ErrorFormat::Json => json(diagnostic),
+        }
+    }
+
+    // error[E0002]: Expect ';' after value
+    //  --> main.lox:3:7
+    //   |
+    // 3 | print a
+    //   |        ^ at end
+    //   = help: insert ';' here
+    pub fn human (&self, d : &Diagnostic) -> String {
+        let (label, style) = match d.severity {
+            Severity::Error => ("error", RED),
+            Severity::Warning => ("warning", YELLOW),
+        };
+        let span = d.span;
+        let gutter = " ".repeat(span.line.to_string().len());
+
+        let mut out = format!(
+            "{}{}\n",
+            self.paint(style, &format!("{}[{}]", label, d.code.as_str())),
+            self.paint(BOLD, &format!(": {}", d.message)),
+        );
+        out.push_str(&format!(
+            "{}{} {}:{}:{}\n",
+            gutter,
+            self.paint(BLUE, "-->"),
+            d.file.as_deref().unwrap_or("<input>"),
+            span.line,
+            span.column,
+        ));
+
+        if let Some(line) = source_line(self.source, span.start) {
+            let bar = self.paint(BLUE, "|");
+            out.push_str(&format!("{} {}\n", gutter, bar));
+            out.push_str(&format!("{} {} {}\n", self.paint(BLUE, &span.line.to_string()), bar, line));
+
+            // underline up to the end of the first line of the span; spans
+            // made outside the scanner (`Span::default()`) have column 0
+            let indent = span.column.saturating_sub(1);
+            let width = line.chars().count().saturating_sub(indent);
+            let len = self.source[span.start..span.end.min(self.source.len())]
+                .chars()
+                .take_while(|c| *c != '\n')
+                .count()
+                .clamp(1, width.max(1));
+            let mut marker = format!("{}{}", " ".repeat(indent), "^".repeat(len));
+            if !d.location.is_empty() {
+                marker.push_str(&d.location);
+            }
+            out.push_str(&format!("{} {} {}\n", gutter, bar, self.paint(style, &marker)));
+        }
+
+        for note in &d.notes {
+            out.push_str(&format!("{} {} {}\n", gutter, self.paint(BLUE, "="), self.paint(BOLD, &format!("note: {}", note))));
+        }
+        if let Some(help) = &d.help {
+            out.push_str(&format!("{} {} {}\n", gutter, self.paint(BLUE, "="), self.paint(CYAN, &format!("help: {}", help))));
+        }
+        out
+    }
+}
+
+// * The full text of the line containing byte `offset`
+fn source_line (source : &str, offset : usize) -> Option<&str> {
+    if offset > source.len() {
+        return None;
+    }
+    let start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let end = source[offset..].find('\n').map_or(source.len(), |i| offset + i);
+    Some(source[start..end].trim_end_matches('\r'))
+}
+
+pub fn json (d : &Diagnostic) -> String {
+    let severity = match d.severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    };
+    let notes = d.notes.iter().map(|n| json_string(n)).collect::<Vec<String>>().join(",");
+    format!(
+        "{{\"severity\":\"{}\",\"code\":\"{}\",\"file\":{},\"line\":{},\"column\":{},\"start\":{},\"end\":{},\"location\":{},\"message\":{},\"notes\":[{}],\"help\":{}}}",
+        severity,
+        d.code.as_str(),
+        d.file.as_deref().map_or("null".to_string(), json_string),
+        d.span.line,
+        d.span.column,
+        d.span.start,
+        d.span.end,
+        json_string(d.location.trim_start()),
+        json_string(&d.message),
+        notes,
+        d.help.as_deref().map_or("null".to_string(), json_string),
+    )
+}
+
+fn json_string (s : &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -3,11 +3,10 @@
 // ? we know how resolve it
 
 use std::collections::HashMap;
-use std::env::var;
-use std::thread::scope;
 
-use crate::error_handler::err;
-use crate::scanner::{Token, LiteralType};
+use crate::scanner::Token;
+use crate::parser::NodeId;
+use crate::symbol::Symbol;
 
 use crate::stmt;
 use crate::{
@@ -23,29 +22,33 @@ enum FunctionType {
     Func,
     Method,
     None,
-    INITIALIZER,
+    Initializer,
 }
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum ClassType {
     Class,
     None,
-    SUBCLASS,
+    Subclass,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum LoopType {
-    Loop,
-    None,
+// ? A local as the resolver sees it: whether its initializer has finished,
+// ? and the slot it occupies in its scope's environment
+#[derive(Debug, Clone, Copy)]
+pub struct Local {
+    pub defined : bool,
+    pub slot : usize,
 }
 
 pub struct Resolver<'a> {
-    pub scopes : Vec<HashMap<String, bool>>,     
+    pub scopes : Vec<HashMap<Symbol, Local>>,
     interpreter : &'a mut Interpreter,
     current_function : FunctionType,
-    current_loop : LoopType,
+    // labels of the loops enclosing the current statement, innermost last
+    loops : Vec<Option<Symbol>>,
     current_class : ClassType,
 
     had_error: bool,
+    errors : Vec<ResolverError>,
 }
 
 impl<'a> Resolver<'a> {
@@ -53,17 +56,18 @@ impl<'a> Resolver<'a> {
     pub fn new (interpreter : &'a mut Interpreter) -> Resolver<'a> {
         Resolver {
             scopes : vec![],
-            interpreter : interpreter,
+            interpreter,
             current_function : FunctionType::None,
-            current_loop : LoopType::None,
+            loops : vec![],
             current_class : ClassType::None,
             had_error: false,
+            errors : vec![],
         }
     }
 
     pub fn resolve (&mut self, statements : &[Stmt]) {
         for stmt in statements {
-            self.resolve_stmt(&stmt);
+            self.resolve_stmt(stmt);
         }
     }
 
@@ -71,11 +75,35 @@ impl<'a> Resolver<'a> {
         self.had_error
     }
 
-    fn resolve_stmt (&mut self, stmt : &Stmt) -> () {
+    pub fn take_errors (&mut self) -> Vec<ResolverError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn error (&mut self, token : &Token, msg : &str) {
+        self.error_with_help(token, msg, None);
+    }
+
+    fn error_with_help (&mut self, token : &Token, msg : &str, help : Option<&str>) {
+        self.had_error = true;
+        self.errors.push(ResolverError {
+            span : token.span,
+            message : msg.to_string(),
+            help : help.map(str::to_string),
+        });
+    }
+
+    fn check_label (&mut self, label : &Token) {
+        if !self.loops.iter().any(|l| l.as_deref() == Some(label.lexeme.as_str())) {
+            let msg = format!("Undefined loop label '{}'", label.lexeme);
+            self.error_with_help(label, &msg, Some("labels name an enclosing loop, as in 'outer: while (...)'"));
+        }
+    }
+
+    fn resolve_stmt (&mut self, stmt : &Stmt) {
         stmt.accept(self)
     }
 
-    fn resolve_expr (&mut self, expr : &Expr) -> () {
+    fn resolve_expr (&mut self, expr : &Expr) {
         expr.accept(self)
     }
 
@@ -89,33 +117,38 @@ impl<'a> Resolver<'a> {
 
     fn declare (&mut self, name : &Token){
         if let Some (scope) = self.scopes.last_mut() {
-            if scope.contains_key(&name.lexeme) {
-                err(name.line, "Variable with this name already declared in this scope");
-                self.had_error = true;
+            // slots follow declaration order, as the interpreter pushes them
+            let local = Local { defined : false, slot : scope.len() };
+            let redeclared = scope.insert(name.lexeme.clone(), local).is_some();
+            if redeclared {
+                self.error_with_help(name, "Variable with this name already declared in this scope", Some("rename the variable or assign to the existing one"));
             }
-            scope.insert(name.lexeme.clone(), false);
         }
     }
 
     fn define (&mut self, name : &Token) {
-        if let Some (scope) = self.scopes.last_mut() {
-            scope.insert(name.lexeme.clone(), true);
+        if let Some (local) = self.scopes.last_mut().and_then(|scope| scope.get_mut(&name.lexeme)) {
+            local.defined = true;
         }
     }
 
-    fn resolve_local (&mut self, expr : &Expr, token : &Token) -> () {
+    fn resolve_local (&mut self, id : NodeId, token : &Token) {
         for (i, scope) in self.scopes.iter().enumerate().rev() {
-            if scope.contains_key(&token.lexeme) {
-                self.interpreter.resolve(expr, self.scopes.len() - 1 - i);
+            if let Some(local) = scope.get(&token.lexeme) {
+                self.interpreter.resolve(id, self.scopes.len() - 1 - i, local.slot);
+                // the innermost declaration wins
+                return;
             }
         }
         // dbg!(&self.scopes);
     }
 
-    fn resolve_function (&mut self, function : &stmt::Function, typ : FunctionType) -> () {
+    fn resolve_function (&mut self, function : &stmt::Function, typ : FunctionType) {
         
         let enclosing_function = self.current_function;
         self.current_function = typ;
+        // break/continue cannot cross a function boundary
+        let enclosing_loops = std::mem::take(&mut self.loops);
         
         self.begin_scope();
         for param in function.params.iter() {
@@ -126,6 +159,7 @@ impl<'a> Resolver<'a> {
         self.end_scope(); 
 
         self.current_function = enclosing_function;
+        self.loops = enclosing_loops;
         
     }
 
@@ -134,48 +168,44 @@ impl<'a> Resolver<'a> {
 
 impl<'a> ExprVisitor <()> for Resolver<'a> {
 
-    fn visit_this(&mut self, this : &crate::expr::This) -> () {
+    fn visit_this(&mut self, this : &crate::expr::This) {
         if self.current_class == ClassType::None {
-            err(this.keyword.line, "Cannot use 'this' outside of a class");
-            self.had_error = true;
+            self.error(&this.keyword, "Cannot use 'this' outside of a class");
             return;
         }
-        self.resolve_local(&Expr::This(this.clone()), &this.keyword);
+        self.resolve_local(this.id, &this.keyword);
     }
 
-    fn visit_superr(&mut self, superr : &crate::expr::Superr) -> () {
+    fn visit_superr(&mut self, superr : &crate::expr::Superr) {
         
         if self.current_class == ClassType::None {
-            err(superr.keyword.line, "Cannot use 'super' outside of a class");
-            self.had_error = true;
-        } else if self.current_class != ClassType::SUBCLASS {
-            err(superr.keyword.line, "Cannot use 'super' in a class with no superclass");
-            self.had_error = true;
+            self.error(&superr.keyword, "Cannot use 'super' outside of a class");
+        } else if self.current_class != ClassType::Subclass {
+            self.error(&superr.keyword, "Cannot use 'super' in a class with no superclass");
         }
 
-        self.resolve_local(&Expr::Superr(superr.clone()), &superr.keyword);
+        self.resolve_local(superr.id, &superr.keyword);
     }
 
-    fn visit_variable(&mut self, variable : &crate::expr::Variable) -> () {
+    fn visit_variable(&mut self, variable : &crate::expr::Variable) {
         
         if let Some (scope) = self.scopes.last() {
-            if let Some (is_defined) = scope.get(&variable.name.lexeme) {
-                if !is_defined {
-                    err(variable.name.line, "Cannot read local variable in its own initializer");
-                    self.had_error = true;
+            if let Some (local) = scope.get(&variable.name.lexeme) {
+                if !local.defined {
+                    self.error_with_help(&variable.name, "Cannot read local variable in its own initializer", Some("give the new variable a different name"));
                 }
             }
         }
-        self.resolve_local(&Expr::Variable(variable.clone()), &variable.name);
+        self.resolve_local(variable.id, &variable.name);
     }
 
-    fn visit_assigment(&mut self, assigment : &crate::expr::Assigment) -> () {
+    fn visit_assigment(&mut self, assigment : &crate::expr::Assigment) {
         self.resolve_expr(&assigment.value);
-        self.resolve_local(&Expr::Assigment(assigment.clone()), &assigment.name);
+        self.resolve_local(assigment.id, &assigment.name);
         
     }
 
-    fn visit_binary(&mut self, binary : &crate::expr::Binary) -> () {
+    fn visit_binary(&mut self, binary : &crate::expr::Binary) {
         self.resolve_expr(&binary.left);
         self.resolve_expr(&binary.right);
         
@@ -194,7 +224,7 @@ impl<'a> ExprVisitor <()> for Resolver<'a> {
         
     }
 
-    fn visit_call(&mut self, call : &crate::expr::Call) -> () {
+    fn visit_call(&mut self, call : &crate::expr::Call) {
         self.resolve_expr(&call.callee);
         for arg in call.arguments.iter() {
             self.resolve_expr(arg);
@@ -202,32 +232,60 @@ impl<'a> ExprVisitor <()> for Resolver<'a> {
         
     }
 
-    fn visit_get(&mut self, get : &crate::expr::Get) -> () {
+    fn visit_get(&mut self, get : &crate::expr::Get) {
         self.resolve_expr(&get.object);
     }
 
-    fn visit_set(&mut self, set : &crate::expr::Set) -> () {
+    fn visit_set(&mut self, set : &crate::expr::Set) {
         self.resolve_expr(&set.object);
         self.resolve_expr(&set.value);
     }
 
-    fn visit_grouping(&mut self, grouping : &crate::expr::Grouping) -> () {
+    fn visit_list(&mut self, list : &crate::expr::List) {
+        for element in list.elements.iter() {
+            self.resolve_expr(element);
+        }
+    }
+
+    fn visit_index(&mut self, index : &crate::expr::Index) {
+        self.resolve_expr(&index.object);
+        self.resolve_expr(&index.index);
+    }
+
+    fn visit_setindex(&mut self, setindex : &crate::expr::SetIndex) {
+        self.resolve_expr(&setindex.object);
+        self.resolve_expr(&setindex.index);
+        self.resolve_expr(&setindex.value);
+    }
+
+    fn visit_map(&mut self, map : &crate::expr::Map) {
+        for (key, value) in map.keys.iter().zip(map.values.iter()) {
+            self.resolve_expr(key);
+            self.resolve_expr(value);
+        }
+    }
+
+    fn visit_lambda(&mut self, lambda : &crate::expr::Lambda) {
+        self.resolve_function(&lambda.declaration, FunctionType::Func);
+    }
+
+    fn visit_grouping(&mut self, grouping : &crate::expr::Grouping) {
         self.resolve_expr(grouping.expression.as_ref());
         
     }
 
-    fn visit_literal(&mut self, literal : &crate::expr::Literal) -> () {
+    fn visit_literal(&mut self, _literal : &crate::expr::Literal) {
         
     }
 
-    fn visit_unary(&mut self, unary : &crate::expr::Unary) -> () {
+    fn visit_unary(&mut self, unary : &crate::expr::Unary) {
         self.resolve_expr(&unary.right);
         
     }
 }
 
 impl<'a> StmtVisitor<()> for Resolver<'a> {
-    fn visit_block(&mut self, block : &crate::stmt::Block) -> () {
+    fn visit_block(&mut self, block : &crate::stmt::Block) {
         self.begin_scope();
         self.resolve (block.statements.as_slice());
 
@@ -235,7 +293,7 @@ impl<'a> StmtVisitor<()> for Resolver<'a> {
         
     }
 
-    fn visit_var(&mut self, var : &stmt::Var) -> () {
+    fn visit_var(&mut self, var : &stmt::Var) {
         self.declare(&var.name);
         if let Some (init) = var.initializer.as_ref() {
             self.resolve_expr(init);
@@ -244,14 +302,14 @@ impl<'a> StmtVisitor<()> for Resolver<'a> {
         
     }
 
-    fn visit_function(&mut self, function : &stmt::Function) -> () {
+    fn visit_function(&mut self, function : &stmt::Function) {
         self.declare(&function.name);
         self.define(&function.name);
 
         self.resolve_function(function, FunctionType::Func);
     }
 
-    fn visit_class(&mut self, class : &stmt::Class) -> () {
+    fn visit_class(&mut self, class : &stmt::Class) {
 
         let enclosing_class = self.current_class;
         self.current_class = ClassType::Class;
@@ -259,28 +317,27 @@ impl<'a> StmtVisitor<()> for Resolver<'a> {
         self.declare(&class.name);
         self.define(&class.name);
 
-        if let Some (Expr::Variable(sup)) = &class.SuperClass{
+        if let Some (Expr::Variable(sup)) = &class.super_class{
 
-            self.current_class = ClassType::SUBCLASS;
+            self.current_class = ClassType::Subclass;
 
             if sup.name.lexeme == class.name.lexeme {
-                err(sup.name.line, "A class cannot inherit from itself");
-                self.had_error = true;
+                self.error(&sup.name, "A class cannot inherit from itself");
             }
 
-            self.resolve_expr(&class.SuperClass.as_ref().unwrap());
+            self.resolve_expr(class.super_class.as_ref().unwrap());
 
             self.begin_scope();
 
-            self.scopes.last_mut().unwrap().insert("super".to_string(), true);
+            self.scopes.last_mut().unwrap().insert(Symbol::intern("super"), Local { defined : true, slot : 0 });
         }
 
         self.begin_scope();
-        self.scopes.last_mut().unwrap().insert("this".to_string(), true);
+        self.scopes.last_mut().unwrap().insert(Symbol::intern("this"), Local { defined : true, slot : 0 });
 
         for method in class.methods.iter() {
             let decl = if method.name.lexeme == "init" {
-                FunctionType::INITIALIZER
+                FunctionType::Initializer
             } else {
                 FunctionType::Method   
             };
@@ -289,19 +346,19 @@ impl<'a> StmtVisitor<()> for Resolver<'a> {
 
         self.end_scope();
 
-        if class.SuperClass.is_some() {
+        if class.super_class.is_some() {
             self.end_scope();
         }
 
         self.current_class = enclosing_class;
     }
 
-    fn visit_expression(&mut self, expression : &stmt::Expression) -> () {
+    fn visit_expression(&mut self, expression : &stmt::Expression) {
         self.resolve_expr(&expression.expression);
         
     }
 
-    fn visit_iff(&mut self, iff : &stmt::Iff) -> () {
+    fn visit_iff(&mut self, iff : &stmt::Iff) {
         self.resolve_expr(&iff.condition);
         self.resolve_stmt(iff.then_branch.as_ref());
         if let Some (else_branch) = iff.else_branch.as_ref() {
@@ -310,23 +367,21 @@ impl<'a> StmtVisitor<()> for Resolver<'a> {
         
     }
 
-    fn visit_print(&mut self, print : &stmt::Print) -> () {
+    fn visit_print(&mut self, print : &stmt::Print) {
 
         self.resolve_expr(&print.expression);
         
     }
 
-    fn visit_returnn(&mut self, returnn : &stmt::Returnn) -> () {
+    fn visit_returnn(&mut self, returnn : &stmt::Returnn) {
         if self.current_function == FunctionType::None {
-            err(returnn.keyword.line, "Cannot use 'return' outside of a function");
-            self.had_error = true;
+            self.error_with_help(&returnn.keyword, "Cannot use 'return' outside of a function", Some("'return' is only allowed inside function and method bodies"));
         }
         
         if let Some (value) = &returnn.value {
         
-            if self.current_class == ClassType::Class && self.current_function == FunctionType::INITIALIZER {
-                err(returnn.keyword.line, "Cannot return a value from an initializer");
-                self.had_error = true;
+            if self.current_class == ClassType::Class && self.current_function == FunctionType::Initializer {
+                self.error(&returnn.keyword, "Cannot return a value from an initializer");
 
             }
 
@@ -334,28 +389,71 @@ impl<'a> StmtVisitor<()> for Resolver<'a> {
         }
     }
 
-    fn visit_breakk(&mut self, breakk : &stmt::Breakk) -> () {
-        if self.current_loop == LoopType::None {
-            err(breakk.keyword.line, "Cannot use 'break' outside of a loop");
-            self.had_error = true;
+    fn visit_breakk(&mut self, breakk : &stmt::Breakk) {
+        if self.loops.is_empty() {
+            self.error_with_help(&breakk.keyword, "Cannot use 'break' outside of a loop", Some("'break' is only allowed inside 'while' and 'for' bodies"));
+        } else if let Some(label) = &breakk.label {
+            self.check_label(label);
         }
         
     }
 
-    fn visit_continuee(&mut self, continuee : &stmt::Continuee) -> () {
-        if self.current_loop == LoopType::None {
-            err(continuee.keyword.line, "Cannot use 'continue' outside of a loop");
-            self.had_error = true;
+    fn visit_continuee(&mut self, continuee : &stmt::Continuee) {
+        if self.loops.is_empty() {
+            self.error_with_help(&continuee.keyword, "Cannot use 'continue' outside of a loop", Some("'continue' is only allowed inside 'while' and 'for' bodies"));
+        } else if let Some(label) = &continuee.label {
+            self.check_label(label);
         }
         
     }
 
-    fn visit_whilee(&mut self, whilee : &stmt::Whilee) -> () {
+    fn visit_throw(&mut self, throw : &stmt::Throw) {
+        self.resolve_expr(&throw.value);
+    }
+
+    fn visit_tryy(&mut self, tryy : &stmt::Tryy) {
+        self.begin_scope();
+        self.resolve(&tryy.body);
+        self.end_scope();
+
+        if let (Some(name), Some(body)) = (&tryy.catch_name, &tryy.catch_body) {
+            // the exception variable lives in the same scope as the catch body
+            self.begin_scope();
+            self.declare(name);
+            self.define(name);
+            self.resolve(body);
+            self.end_scope();
+        }
+
+        if let Some(body) = &tryy.finally_body {
+            self.begin_scope();
+            self.resolve(body);
+            self.end_scope();
+        }
+    }
+
+    fn visit_whilee(&mut self, whilee : &stmt::Whilee) {
         self.resolve_expr(whilee.condition.as_ref()); 
 
-        self.current_loop = LoopType::Loop;
+        self.loops.push(whilee.label.as_ref().map(|l| l.lexeme.clone()));
         self.resolve_stmt(whilee.body.as_ref());
+        if let Some(increment) = &whilee.increment {
+            self.resolve_expr(increment);
+        }
+        self.loops.pop();
+    }
+
+    // * The loop variable lives in its own scope, which the interpreter
+    // * recreates on every iteration
+    fn visit_forin(&mut self, forin : &stmt::ForIn) {
+        self.resolve_expr(forin.iterable.as_ref());
 
-        self.current_loop = LoopType::None;   
+        self.loops.push(forin.label.as_ref().map(|l| l.lexeme.clone()));
+        self.begin_scope();
+        self.declare(&forin.name);
+        self.define(&forin.name);
+        self.resolve_stmt(forin.body.as_ref());
+        self.end_scope();
+        self.loops.pop();
     }
 }
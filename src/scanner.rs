@@ -1,25 +1,29 @@
 use::std::fmt::Display;
 use lazy_static::lazy_static;
 use std::collections::HashMap;
+use std::cell::RefCell;
+use std::rc::Rc;
 
-use crate::error_handler::{err, ScannerError};
+use crate::error_handler::ScannerError;
 use crate::loxcallable::Callable;
+use crate::map::LoxMap;
+use crate::symbol::Symbol;
 
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenType {
     // Single char
-    LeftParen, RightParan, LeftBrac, RightBrace, Comma, Dot, Colon, Minus, Plus, Semicolon,  Slash, Star, QuestionMark, Percentage,
+    LeftParen, RightParan, LeftBrac, RightBrace, LeftBracket, RightBracket, Comma, Dot, Colon, Minus, Plus, Semicolon,  Slash, Star, QuestionMark, Percentage,
 
     // One or two char
-    Bang, BangEqual, Equal, EqualEqual, Greater, GreaterEqual, Less, LessEqual,
+    Bang, BangEqual, Equal, EqualEqual, Greater, GreaterEqual, Less, LessEqual, Arrow,
 
     // Literals
     Identifier, String, Number,
     
     // Keywords
     And, Class, Else, False, Fun, For, If, Nil, Or, Print, Return, Super, This, True, Var, While,
-    Break, Continue,
+    Break, Continue, Throw, Try, Catch, Finally, In,
 
     // End of file
     EOF,
@@ -27,11 +31,57 @@ pub enum TokenType {
 
 #[derive(Debug, Clone)]
 pub enum LiteralType {
-    String(String),
+    // * shared, so copying a string value never copies its characters
+    String(Rc<str>),
     Number(f64),
     Bool (bool),
     Nil,
     Callable(Callable),
+    List(Rc<RefCell<Vec<LiteralType>>>),
+    Map(Rc<RefCell<LoxMap>>),
+    // * `range(start, end)`: start inclusive, end exclusive
+    Range(f64, f64),
+}
+
+impl LiteralType {
+    // * How a value looks inside a collection: strings keep their quotes
+    pub fn repr (&self) -> String {
+        match self {
+            LiteralType::String(s) => format!("{:?}", s),
+            _ => self.to_string(),
+        }
+    }
+
+    // * `printing` holds the lists and maps being printed further out; one that
+    // * shows up again contains itself, and prints as `[...]` or `{...}`
+    fn render (&self, printing : &mut Vec<usize>) -> String {
+        match self {
+            LiteralType::String(s) => format!("{:?}", s),
+            LiteralType::List(l) => {
+                let address = Rc::as_ptr(l) as usize;
+                if printing.contains(&address) {
+                    return "[...]".to_string();
+                }
+                printing.push(address);
+                let items = l.borrow().iter().map(|v| v.render(printing)).collect::<Vec<String>>();
+                printing.pop();
+                format!("[{}]", items.join(", "))
+            }
+            LiteralType::Map(m) => {
+                let address = Rc::as_ptr(m) as usize;
+                if printing.contains(&address) {
+                    return "{...}".to_string();
+                }
+                printing.push(address);
+                let items = m.borrow().iter()
+                    .map(|(k, v)| format!("{}: {}", k.to_value().render(printing), v.render(printing)))
+                    .collect::<Vec<String>>();
+                printing.pop();
+                format!("{{{}}}", items.join(", "))
+            }
+            _ => self.to_string(),
+        }
+    }
 }
 
 impl Display for LiteralType {
@@ -41,7 +91,9 @@ impl Display for LiteralType {
             LiteralType::Number(n) => write!(f, "{}", n),
             LiteralType::Bool(b) => write!(f, "{}", b),
             LiteralType::Nil => write!(f, "nil"),
-            LiteralType::Callable(c) => write!(f, "{:?}", c),
+            LiteralType::Callable(c) => write!(f, "{}", c),
+            LiteralType::List(_) | LiteralType::Map(_) => write!(f, "{}", self.render(&mut vec![])),
+            LiteralType::Range(start, end) => write!(f, "range({}, {})", start, end),
         }
     }
 }
@@ -67,17 +119,52 @@ lazy_static! {
         ("while", TokenType::While),
         ("break", TokenType::Break),
         ("continue", TokenType::Continue),
+        ("throw", TokenType::Throw),
+        ("try", TokenType::Try),
+        ("catch", TokenType::Catch),
+        ("finally", TokenType::Finally),
+        ("in", TokenType::In),
     ]);
 }
 
 impl Copy for TokenType {}
 
+// ? Source range of a token or AST node: byte offsets into the source
+// ? plus the (1-based) line and column the range starts on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start : usize,
+    pub end : usize,
+    pub line : i32,
+    pub column : usize,
+}
+
+impl Span {
+    // * Range covering both `self` and `other`, starting where `self` starts
+    pub fn to (&self, other : Span) -> Span {
+        Span {
+            start : self.start,
+            end : other.end.max(self.end),
+            line : self.line,
+            column : self.column,
+        }
+    }
+
+    pub fn len (&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty (&self) -> bool {
+        self.start == self.end
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Token {
     pub token_type: TokenType,
-    pub lexeme: String,
+    pub lexeme: Symbol,
     pub literal: LiteralType,
-    pub line: i32,
+    pub span: Span,
 }
 
 impl Display for Token {
@@ -94,33 +181,78 @@ pub struct Scanner {
     start : i32,
     current : i32,
     line : i32,
+
+    // byte offsets backing the token spans
+    start_byte : usize,
+    current_byte : usize,
+    line_start : usize,
+    start_line : i32,
+
+    errors : Vec<ScannerError>,
 }
 
 impl Scanner {
     pub fn new(source: String) -> Scanner {
         Scanner {
-            source: source,
+            source,
             start: 0,
             current: 0,
             line: 1,
+            start_byte: 0,
+            current_byte: 0,
+            line_start: 0,
+            start_line: 1,
             tokens: vec![],
+            errors: vec![],
         }
     }
 }
 
 fn is_at_end (scanner : &mut Scanner) -> bool {
-    return scanner.current >= scanner.source.len().try_into().unwrap();
+    scanner.current_byte >= scanner.source.len()
+}
+
+fn new_line (scanner : &mut Scanner) {
+    scanner.line += 1;
+    scanner.line_start = scanner.current_byte;
+}
+
+// * Span from the start of the current lexeme up to the scanner position
+fn current_span (scanner : &Scanner) -> Span {
+    let column = if scanner.start_byte >= scanner.line_start {
+        scanner.source[scanner.line_start..scanner.start_byte].chars().count() + 1
+    } else {
+        // the lexeme started on an earlier line (multi-line strings)
+        let line_start = scanner.source[..scanner.start_byte].rfind('\n').map_or(0, |i| i + 1);
+        scanner.source[line_start..scanner.start_byte].chars().count() + 1
+    };
+    Span {
+        start : scanner.start_byte,
+        end : scanner.current_byte,
+        line : scanner.start_line,
+        column,
+    }
 }
 
-fn scan_token (scanner : &mut Scanner) -> bool {
+fn error (scanner : &mut Scanner, msg : &str, help : Option<&str>) {
+    let span = current_span(scanner);
+    scanner.errors.push(ScannerError {
+        span,
+        message : msg.to_string(),
+        help : help.map(str::to_string),
+    });
+}
+
+fn scan_token (scanner : &mut Scanner) {
     let c = advance(scanner);
-    let mut err_code = false;
     match c {
         // simple one char tokens
         '(' => _add_token(scanner, TokenType::LeftParen),
         ')' => _add_token(scanner, TokenType::RightParan),
         '{' => _add_token(scanner, TokenType::LeftBrac),
         '}' => _add_token(scanner, TokenType::RightBrace),
+        '[' => _add_token(scanner, TokenType::LeftBracket),
+        ']' => _add_token(scanner, TokenType::RightBracket),
         ',' => _add_token(scanner, TokenType::Comma),
         ':' => _add_token(scanner, TokenType::Colon),
         '.' => _add_token(scanner, TokenType::Dot),
@@ -133,7 +265,7 @@ fn scan_token (scanner : &mut Scanner) -> bool {
 
         // two chat tokenss
         '!' => if check_next(scanner, '=') { _add_token(scanner, TokenType::BangEqual) } else { _add_token(scanner, TokenType::Bang) },
-        '=' => if check_next(scanner, '=') { _add_token(scanner, TokenType::EqualEqual) } else { _add_token(scanner, TokenType::Equal) },
+        '=' => if check_next(scanner, '=') { _add_token(scanner, TokenType::EqualEqual) } else if check_next(scanner, '>') { _add_token(scanner, TokenType::Arrow) } else { _add_token(scanner, TokenType::Equal) },
         '<' => if check_next(scanner, '=') { _add_token(scanner, TokenType::LessEqual) } else { _add_token(scanner, TokenType::Less) },
         '>' => if check_next(scanner, '=') { _add_token(scanner, TokenType::GreaterEqual) } else { _add_token(scanner, TokenType::Greater) },
  
@@ -147,29 +279,27 @@ fn scan_token (scanner : &mut Scanner) -> bool {
             _add_token(scanner, TokenType::Slash);
         },
 
-        '\n' => {scanner.line += 1;},
+        '\n' => {new_line(scanner);},
         
         // whitespace
         c if c.is_whitespace() => {}
 
         // Literals
         '"' => {string(scanner);}
-        c if c.is_digit(10) => {number(scanner);}
+        c if c.is_ascii_digit() => {number(scanner);}
         _ => {
 
             if c.is_ascii_alphabetic() {
                 identifier(scanner);
             } else {
-                err(scanner.line, "Unexpected character");
-                err_code = true;
+                error(scanner, "Unexpected character", None);
             }
         }
     }
-    return err_code;
 }
 
 fn _add_token (scanner : &mut Scanner, token_type : TokenType)  {
-    return add_token(scanner, token_type, LiteralType::Nil);
+    add_token(scanner, token_type, LiteralType::Nil)
 }
 
 fn add_token (scanner : &mut Scanner, token_type : TokenType, literal : LiteralType) {
@@ -178,14 +308,21 @@ fn add_token (scanner : &mut Scanner, token_type : TokenType, literal : LiteralT
         .take((scanner.current - scanner.start) as usize)
         .collect::<String>();
 
-    scanner.tokens.push(Token{token_type, lexeme: chrs, literal, line: scanner.line});
+    // the interner is never freed, so unbounded literal text stays out of it
+    let lexeme = match token_type {
+        TokenType::String | TokenType::Number => Symbol::uninterned(&chrs),
+        _ => Symbol::intern(&chrs),
+    };
+    let span = current_span(scanner);
+    scanner.tokens.push(Token{token_type, lexeme, literal, span});
 }
 
 
 fn advance (scanner : &mut Scanner) -> char {
     let c = scanner.source.chars().nth(scanner.current as usize).unwrap();
     scanner.current += 1;
-    return c;
+    scanner.current_byte += c.len_utf8();
+    c
 }
 
 fn check_next (scanner : &mut Scanner, expected : char) -> bool {
@@ -193,38 +330,28 @@ fn check_next (scanner : &mut Scanner, expected : char) -> bool {
         Some(c) => {
             if c == expected {
                 scanner.current += 1;
+                scanner.current_byte += c.len_utf8();
                 return true;
             }
-            return false;
+            false
         },
-        None => return false,
+        None => false,
     }
 }
 
 fn peek (scanner : &mut Scanner) -> char {
-    match scanner.source.chars().nth(scanner.current as usize) {
-        Some(c) => return c,
-        None => return '\0',
-    }
-}
-
-fn peek_next (scanner : &mut Scanner) -> char {
-    if (scanner.current + 1) >= scanner.source.len().try_into().unwrap() {
-        return '\0';
-    }
-    return scanner.source.chars().nth((scanner.current + 1) as usize).unwrap();
+    scanner.source.chars().nth(scanner.current as usize).unwrap_or('\0')
 }
 
 // helper functions to get parsing Literals
 fn string (scanner : &mut Scanner) {
     while peek(scanner) != '"' && !is_at_end(scanner) {
-        if peek(scanner) == '\n' {
-            scanner.line += 1;
+        if advance(scanner) == '\n' {
+            new_line(scanner);
         }
-        advance(scanner);
     }
     if is_at_end(scanner) {
-        err(scanner.line, "Unterminated string");
+        error(scanner, "Unterminated string", Some("add a closing '\"' to end the string"));
         return;
     }
     // close the string
@@ -234,13 +361,12 @@ fn string (scanner : &mut Scanner) {
         .skip(scanner.start as usize + 1)
         .take((scanner.current - scanner.start - 2) as usize)
         .collect::<String>();
-    add_token(scanner, TokenType::String, LiteralType::String(value));
+    add_token(scanner, TokenType::String, LiteralType::String(value.into()));
 }
 
 // * Supports trailing dot
 fn number (scanner : &mut Scanner) {
-    let mut dot_offset = 0;
-    while peek(scanner).is_digit(10) {
+    while peek(scanner).is_ascii_digit() {
         advance(scanner);
     }
     let int_part = scanner.source.chars()
@@ -250,13 +376,13 @@ fn number (scanner : &mut Scanner) {
         .parse::<i32>().unwrap_or_default();
     if peek(scanner) == '.' {
         advance(scanner);
-        dot_offset = scanner.current;
     } else {
         add_token(scanner, TokenType::Number, LiteralType::Number(int_part as f64));
         return;
     }
 
-    while peek(scanner).is_digit(10) {
+    let dot_offset = scanner.current;
+    while peek(scanner).is_ascii_digit() {
         advance(scanner);
     }
 
@@ -268,7 +394,7 @@ fn number (scanner : &mut Scanner) {
         .collect::<String>()
         .parse().unwrap_or_default();
 
-    add_token(scanner, TokenType::Number, LiteralType::Number(int_part as f64 + frac_part as f64 / 10_f64.powi(num_of_digits as i32)));
+    add_token(scanner, TokenType::Number, LiteralType::Number(int_part as f64 + frac_part as f64 / 10_f64.powi(num_of_digits)));
 }
 
 fn identifier (scanner : &mut Scanner) {
@@ -284,25 +410,28 @@ fn identifier (scanner : &mut Scanner) {
     match KEYWORDS.get(&text.as_str()) {
         Some(token_type) => {
             _add_token(scanner, *token_type);
-            return;
         },
         None => {
-            add_token(scanner, TokenType::Identifier, LiteralType::String(text));
+            add_token(scanner, TokenType::Identifier, LiteralType::String(text.into()));
         }
     } 
 }
 
 impl Scanner {
-    pub fn scan_tokens(&mut self) -> Result<Vec<Token>, ScannerError> {
+    // * Keeps scanning after a bad character so every error is reported at once
+    // * The tokens, and the errors for whatever it could not make tokens of.
+    // * The parser still runs over the tokens, so its errors show up as well
+    pub fn scan_tokens(&mut self) -> (Vec<Token>, Vec<ScannerError>) {
         while !is_at_end(self) {
             self.start = self.current;
-            match scan_token(self) {
-                true => return Err(ScannerError{line: self.line, message: "Error scanning token".to_string()}),
-                false => {},
-            }
+            self.start_byte = self.current_byte;
+            self.start_line = self.line;
+            scan_token(self);
         }
-        let eof = Token{token_type: TokenType::EOF, lexeme: "".to_string(), literal: LiteralType::Nil, line: self.line};
+        self.start_byte = self.current_byte;
+        self.start_line = self.line;
+        let eof = Token{token_type: TokenType::EOF, lexeme: Symbol::intern(""), literal: LiteralType::Nil, span: current_span(self)};
         self.tokens.push(eof);
-        return Ok(self.tokens.clone());
+        (self.tokens.clone(), std::mem::take(&mut self.errors))
     }
 }
\ No newline at end of file
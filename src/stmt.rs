@@ -1,5 +1,5 @@
 use crate::expr::Expr;
-use crate::scanner::{Token, LiteralType};
+use crate::scanner::{Token, Span};
 #[derive(Debug, Clone)]
 pub enum Stmt {
     Expression (Expression),
@@ -9,64 +9,105 @@ pub enum Stmt {
     Block (Block),
     Iff (Iff),
     Whilee (Whilee),
+    ForIn (ForIn),
     Breakk (Breakk),
     Continuee (Continuee),
     Returnn (Returnn),
     Class (Class),
+    Throw (Throw),
+    Tryy (Tryy),
 }
 #[derive(Debug, Clone)]
 pub struct Expression {
     pub expression : Box<Expr>,
+    pub span : Span,
 }
 #[derive(Debug, Clone)]
 pub struct Function {
     pub name : Token,
     pub params : Vec<Token>,
     pub body : Vec<Stmt>,
+    pub span : Span,
 }
 #[derive(Debug, Clone)]
 pub struct Print {
     pub expression : Box<Expr>,
+    pub span : Span,
 }
 #[derive(Debug, Clone)]
 pub struct Var {
     pub name : Token,
     pub initializer : Option<Expr>,
+    pub span : Span,
 }
 #[derive(Debug, Clone)]
 pub struct Block {
     pub statements : Vec<Stmt>,
+    pub span : Span,
 }
 #[derive(Debug, Clone)]
 pub struct Iff {
     pub condition : Box<Expr>,
     pub then_branch : Box<Stmt>,
     pub else_branch : Option<Box<Stmt>>,
+    pub span : Span,
 }
 #[derive(Debug, Clone)]
 pub struct Whilee {
+    pub label : Option<Token>,
     pub condition : Box<Expr>,
     pub body : Box<Stmt>,
-    pub is_for : bool,
+    pub increment : Option<Expr>,
+    pub span : Span,
+}
+#[derive(Debug, Clone)]
+pub struct ForIn {
+    pub label : Option<Token>,
+    pub name : Token,
+    pub keyword : Token,
+    pub iterable : Box<Expr>,
+    pub body : Box<Stmt>,
+    pub span : Span,
 }
 #[derive(Debug, Clone)]
 pub struct Breakk {
     pub keyword : Token,
+    pub label : Option<Token>,
+    pub span : Span,
 }
 #[derive(Debug, Clone)]
 pub struct Continuee {
     pub keyword : Token,
+    pub label : Option<Token>,
+    pub span : Span,
 }
 #[derive(Debug, Clone)]
 pub struct Returnn {
     pub keyword : Token,
     pub value : Option<Expr>,
+    pub span : Span,
 }
 #[derive(Debug, Clone)]
 pub struct Class {
     pub name : Token,
-    pub SuperClass : Option<Expr>,
+    pub super_class : Option<Expr>,
     pub methods : Vec<Function>,
+    pub span : Span,
+}
+#[derive(Debug, Clone)]
+pub struct Throw {
+    pub keyword : Token,
+    pub value : Box<Expr>,
+    pub span : Span,
+}
+#[derive(Debug, Clone)]
+pub struct Tryy {
+    pub keyword : Token,
+    pub body : Vec<Stmt>,
+    pub catch_name : Option<Token>,
+    pub catch_body : Option<Vec<Stmt>>,
+    pub finally_body : Option<Vec<Stmt>>,
+    pub span : Span,
 }
 pub trait Visitor<T> {
     fn visit_expression(&mut self, expression : &Expression) -> T;
@@ -76,10 +117,13 @@ pub trait Visitor<T> {
     fn visit_block(&mut self, block : &Block) -> T;
     fn visit_iff(&mut self, iff : &Iff) -> T;
     fn visit_whilee(&mut self, whilee : &Whilee) -> T;
+    fn visit_forin(&mut self, forin : &ForIn) -> T;
     fn visit_breakk(&mut self, breakk : &Breakk) -> T;
     fn visit_continuee(&mut self, continuee : &Continuee) -> T;
     fn visit_returnn(&mut self, returnn : &Returnn) -> T;
     fn visit_class(&mut self, class : &Class) -> T;
+    fn visit_throw(&mut self, throw : &Throw) -> T;
+    fn visit_tryy(&mut self, tryy : &Tryy) -> T;
 }
 impl Stmt {
     pub fn accept<T>(&self, visitor : &mut dyn Visitor<T>) -> T {
@@ -91,10 +135,31 @@ impl Stmt {
             Stmt::Block (block) => visitor.visit_block(block),
             Stmt::Iff (iff) => visitor.visit_iff(iff),
             Stmt::Whilee (whilee) => visitor.visit_whilee(whilee),
+            Stmt::ForIn (forin) => visitor.visit_forin(forin),
             Stmt::Breakk (breakk) => visitor.visit_breakk(breakk),
             Stmt::Continuee (continuee) => visitor.visit_continuee(continuee),
             Stmt::Returnn (returnn) => visitor.visit_returnn(returnn),
             Stmt::Class (class) => visitor.visit_class(class),
+            Stmt::Throw (throw) => visitor.visit_throw(throw),
+            Stmt::Tryy (tryy) => visitor.visit_tryy(tryy),
+          }
+      }
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Expression (e) => e.span,
+            Stmt::Function (e) => e.span,
+            Stmt::Print (e) => e.span,
+            Stmt::Var (e) => e.span,
+            Stmt::Block (e) => e.span,
+            Stmt::Iff (e) => e.span,
+            Stmt::Whilee (e) => e.span,
+            Stmt::ForIn (e) => e.span,
+            Stmt::Breakk (e) => e.span,
+            Stmt::Continuee (e) => e.span,
+            Stmt::Returnn (e) => e.span,
+            Stmt::Class (e) => e.span,
+            Stmt::Throw (e) => e.span,
+            Stmt::Tryy (e) => e.span,
           }
       }
 }
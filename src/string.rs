@@ -0,0 +1,156 @@
+// ? Native methods on string values (`s.upper()`, `s.split(",")`, ...) and the
+// ? string conversion natives `str`, `num`, `chr` and `ord`.
+// ? Indices count characters, not bytes
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::interpreter::Interpreter;
+use crate::list::to_index;
+use crate::loxcallable::{Arity, NativeMethod, NativeMethodFn};
+use crate::scanner::LiteralType;
+
+// * Looks up `name` on `string`, returning the method bound to it
+pub fn method (string : &Rc<str>, name : &str) -> Option<NativeMethod> {
+    let (arity, function) : (usize, NativeMethodFn) = match name {
+        "len" => (0, len),
+        "substring" => (2, substring),
+        "indexOf" => (1, index_of),
+        "split" => (1, split),
+        "trim" => (0, trim),
+        "upper" => (0, upper),
+        "lower" => (0, lower),
+        "replace" => (2, replace),
+        "startsWith" => (1, starts_with),
+        "chars" => (0, chars),
+        _ => return None,
+    };
+    Some(NativeMethod {
+        name : name.to_string(),
+        receiver : Box::new(LiteralType::String(Rc::clone(string))),
+        arity : Arity::Exact(arity),
+        function,
+    })
+}
+
+fn text (receiver : &LiteralType) -> &str {
+    match receiver {
+        LiteralType::String(s) => s,
+        _ => unreachable!("string method bound to a non-string"),
+    }
+}
+
+fn string_arg (value : &LiteralType) -> Result<&str, String> {
+    match value {
+        LiteralType::String(s) => Ok(s),
+        _ => Err(format!("Expected a string but got {}", value.repr())),
+    }
+}
+
+fn string_list (items : impl Iterator<Item = String>) -> LiteralType {
+    let items = items.map(|item| LiteralType::String(item.into())).collect();
+    LiteralType::List(Rc::new(RefCell::new(items)))
+}
+
+fn len (_interpreter : &mut Interpreter, receiver : &LiteralType, _args : &[LiteralType]) -> Result<LiteralType, String> {
+    Ok(LiteralType::Number(text(receiver).chars().count() as f64))
+}
+
+// * Characters in `start..end`
+fn substring (_interpreter : &mut Interpreter, receiver : &LiteralType, args : &[LiteralType]) -> Result<LiteralType, String> {
+    let s = text(receiver);
+    let count = s.chars().count();
+    let start = to_index(&args[0], count)?;
+    let end = to_index(&args[1], count)?;
+    if start > end {
+        return Err("Substring start must not be after its end".to_string());
+    }
+    Ok(LiteralType::String(s.chars().skip(start).take(end - start).collect::<String>().into()))
+}
+
+// * Character index of the first occurrence, -1 if there is none
+fn index_of (_interpreter : &mut Interpreter, receiver : &LiteralType, args : &[LiteralType]) -> Result<LiteralType, String> {
+    let s = text(receiver);
+    let index = match s.find(string_arg(&args[0])?) {
+        Some(byte) => s[..byte].chars().count() as f64,
+        None => -1.0,
+    };
+    Ok(LiteralType::Number(index))
+}
+
+// * An empty separator splits into characters
+fn split (_interpreter : &mut Interpreter, receiver : &LiteralType, args : &[LiteralType]) -> Result<LiteralType, String> {
+    let s = text(receiver);
+    let separator = string_arg(&args[0])?;
+    if separator.is_empty() {
+        return Ok(string_list(s.chars().map(String::from)));
+    }
+    Ok(string_list(s.split(separator).map(String::from)))
+}
+
+fn trim (_interpreter : &mut Interpreter, receiver : &LiteralType, _args : &[LiteralType]) -> Result<LiteralType, String> {
+    Ok(LiteralType::String(text(receiver).trim().into()))
+}
+
+fn upper (_interpreter : &mut Interpreter, receiver : &LiteralType, _args : &[LiteralType]) -> Result<LiteralType, String> {
+    Ok(LiteralType::String(text(receiver).to_uppercase().into()))
+}
+
+fn lower (_interpreter : &mut Interpreter, receiver : &LiteralType, _args : &[LiteralType]) -> Result<LiteralType, String> {
+    Ok(LiteralType::String(text(receiver).to_lowercase().into()))
+}
+
+// * Replaces every occurrence
+fn replace (_interpreter : &mut Interpreter, receiver : &LiteralType, args : &[LiteralType]) -> Result<LiteralType, String> {
+    let from = string_arg(&args[0])?;
+    let to = string_arg(&args[1])?;
+    if from.is_empty() {
+        return Err("Cannot replace an empty string".to_string());
+    }
+    Ok(LiteralType::String(text(receiver).replace(from, to).into()))
+}
+
+fn starts_with (_interpreter : &mut Interpreter, receiver : &LiteralType, args : &[LiteralType]) -> Result<LiteralType, String> {
+    Ok(LiteralType::Bool(text(receiver).starts_with(string_arg(&args[0])?)))
+}
+
+fn chars (_interpreter : &mut Interpreter, receiver : &LiteralType, _args : &[LiteralType]) -> Result<LiteralType, String> {
+    Ok(string_list(text(receiver).chars().map(String::from)))
+}
+
+// str(x): x as print would show it
+pub fn str (_interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    Ok(LiteralType::String(args[0].to_string().into()))
+}
+
+// num(s): parses a number, surrounding whitespace allowed
+pub fn num (_interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    match &args[0] {
+        LiteralType::Number(n) => Ok(LiteralType::Number(*n)),
+        LiteralType::String(s) => s.trim().parse::<f64>()
+            .map(LiteralType::Number)
+            .map_err(|_| format!("Cannot convert {:?} to a number", s)),
+        other => Err(format!("Cannot convert {} to a number", other.repr())),
+    }
+}
+
+// chr(n): the character with code point n
+pub fn chr (_interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    match &args[0] {
+        LiteralType::Number(n) if n.fract() == 0.0 && *n >= 0.0 => match char::from_u32(*n as u32) {
+            Some(c) => Ok(LiteralType::String(c.to_string().into())),
+            None => Err(format!("{} is not a valid character code", n)),
+        },
+        other => Err(format!("{} is not a valid character code", other.repr())),
+    }
+}
+
+// ord(c): the code point of a one-character string
+pub fn ord (_interpreter : &mut Interpreter, args : &[LiteralType]) -> Result<LiteralType, String> {
+    let s = string_arg(&args[0])?;
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(LiteralType::Number(c as u32 as f64)),
+        _ => Err(format!("Expected a single character but got {:?}", s)),
+    }
+}
@@ -0,0 +1,94 @@
+// ? Interned identifiers. Every `Symbol` for the same name shares a single
+// ? allocation, so cloning one is a reference count bump, and comparing or
+// ? hashing it looks at the pointer rather than the characters
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt::{Debug, Display};
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::rc::Rc;
+
+thread_local! {
+    // names live as long as the thread; programs only have so many
+    static INTERNER : RefCell<HashSet<Rc<str>>> = RefCell::new(HashSet::new());
+}
+
+#[derive(Clone)]
+pub struct Symbol (Rc<str>);
+
+impl Symbol {
+    pub fn intern (name : &str) -> Symbol {
+        INTERNER.with(|interner| {
+            let mut interner = interner.borrow_mut();
+            if let Some(interned) = interner.get(name) {
+                return Symbol(Rc::clone(interned));
+            }
+            let interned : Rc<str> = Rc::from(name);
+            interner.insert(Rc::clone(&interned));
+            Symbol(interned)
+        })
+    }
+
+    // * Symbol that bypasses the interner, for text that is never looked up by
+    // * name (string and number literal lexemes). It is equal only to its clones
+    pub fn uninterned (text : &str) -> Symbol {
+        Symbol(Rc::from(text))
+    }
+
+    pub fn as_str (&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq (&self, other : &Symbol) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H : Hasher> (&self, state : &mut H) {
+        (Rc::as_ptr(&self.0) as *const u8).hash(state)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq (&self, other : &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq (&self, other : &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref (&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from (name : &str) -> Symbol {
+        Symbol::intern(name)
+    }
+}
+
+impl Display for Symbol {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
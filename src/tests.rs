@@ -2,24 +2,32 @@
 
 #[cfg(test)]
 mod test {
-    use crate::parser::Parser;
-    use crate::scanner::{Scanner, Token};
+    use crate::parser::{AstPrinter, NodeId, Parser};
+    use crate::scanner::{LiteralType, Scanner, Span};
+    use crate::stmt::Stmt;
+    use crate::render::{self, ErrorFormat, Renderer};
     use crate::interpreter::Interpreter;
+    use crate::loxcallable::{Arity, Callable};
+    use crate::lox::{Backend, Lox};
+    use crate::symbol::Symbol;
+    use crate::math::Random;
+    use crate::error_handler::{ErrorCode, Severity};
+    use std::cell::RefCell;
+    use std::io::Write;
+    use std::rc::Rc;
 
     #[test]
     fn simple_scan () {
         let mut s = Scanner::new("1 + 2".to_string());
-        let tokens = s.scan_tokens();
-        if let Ok(tokens) = tokens {
-            assert_eq!(tokens.len(), 4);
-        }
-            
+        let (tokens, errors) = s.scan_tokens();
+        assert!(errors.is_empty());
+        assert_eq!(tokens.len(), 4);
     }
     #[test]
     fn addition () {
         let mut s = Scanner::new("print 1 + 2;".to_string());
-        let tokens = s.scan_tokens();
-        if let Ok(tokens) = tokens {
+        let (tokens, errors) = s.scan_tokens();
+        if errors.is_empty() {
             let mut parser = Parser::new(tokens);
             match parser.parse() {
                 Ok(stmts) => {
@@ -32,4 +40,938 @@ mod test {
             }
         }    
     }
-}
\ No newline at end of file
+
+    // * The single expression statement in `source`, as `AstPrinter` prints it
+    fn printed (source : &str) -> String {
+        let tokens = Scanner::new(source.to_string()).scan_tokens().0;
+        match Parser::new(tokens).parse().unwrap().remove(0) {
+            Stmt::Expression(e) => e.expression.accept(&mut AstPrinter {}),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn print_list_expressions () {
+        assert_eq!(printed("[1, 2 + 3];"), "(list 1 (+ 2 3))");
+        assert_eq!(printed("[1][0];"), "([] (list 1) 0)");
+        assert_eq!(printed("[1][0] = 2;"), "([]= (list 1) 0 2)");
+    }
+
+    #[test]
+    fn print_lambda_expressions () {
+        assert_eq!(printed("(a, b) => 1 + 2;"), "(fun (a b) (+ 1 2))");
+        assert_eq!(printed("fun () { print 1; };"), "(fun ())");
+    }
+
+    #[test]
+    fn print_map_expressions () {
+        assert_eq!(printed("({\"a\": 1, 2: 3});"), "(group (map a 1 2 3))");
+    }
+
+    #[test]
+    fn embedding_returns_value () {
+        let mut lox = Lox::new();
+        let result = lox.run("var a = 1; a + 2;");
+        assert!(result.is_ok());
+        assert_eq!(result.value.map(|v| v.to_string()), Some("3".to_string()));
+    }
+
+    #[test]
+    fn embedding_keeps_state_between_runs () {
+        let mut lox = Lox::new();
+        lox.run("var greeting = \"hi\";");
+        let result = lox.run("greeting;");
+        assert_eq!(result.value.map(|v| v.to_string()), Some("hi".to_string()));
+    }
+
+    #[test]
+    fn embedding_returns_runtime_error () {
+        let mut lox = Lox::new();
+        let result = lox.run("1 - \"a\";");
+        assert!(result.value.is_none());
+        let error = result.runtime_error.expect("expected a runtime error");
+        assert_eq!(error.message, "Operands must be numbers");
+        assert_eq!(error.token.span.line, 1);
+    }
+
+    #[test]
+    fn embedding_returns_diagnostics () {
+        let mut lox = Lox::new();
+        let result = lox.run("return 1;");
+        assert_eq!(result.diagnostics.len(), 1);
+        assert!(result.runtime_error.is_none());
+    }
+
+    #[test]
+    fn diagnostics_are_structured () {
+        let mut lox = Lox::new();
+        let result = lox.run_source("class A < A {}", Some("inherit.lox"));
+        let d = result.diagnostics.iter().next().expect("expected a diagnostic");
+        assert_eq!(d.severity, Severity::Error);
+        assert_eq!(d.code, ErrorCode::Resolver);
+        assert_eq!(d.file.as_deref(), Some("inherit.lox"));
+        assert_eq!(d.to_string(), "[line 1] Error : A class cannot inherit from itself");
+    }
+
+    #[test]
+    fn parse_diagnostic_keeps_location () {
+        let mut lox = Lox::new();
+        let result = lox.run("print 1");
+        let d = result.diagnostics.iter().next().expect("expected a diagnostic");
+        assert_eq!(d.code, ErrorCode::Parser);
+        assert_eq!(d.to_string(), "[line 1] Error  at end: Expect ';' after value");
+    }
+
+    #[test]
+    fn scanner_diagnostic () {
+        let mut lox = Lox::new();
+        let result = lox.run("var a = \"open;");
+        assert!(result.diagnostics.has_errors());
+        assert_eq!(result.diagnostics.iter().next().unwrap().message, "Unterminated string");
+    }
+
+    #[test]
+    fn token_spans () {
+        let mut s = Scanner::new("var a = 1;\n  print \"é\" + a;".to_string());
+        let tokens = s.scan_tokens().0;
+        assert_eq!(tokens[1].span, Span { start : 4, end : 5, line : 1, column : 5 });
+        // `print` on the second line, after two spaces of indentation
+        assert_eq!(tokens[5].span, Span { start : 13, end : 18, line : 2, column : 3 });
+        // multi-byte string literal: byte offsets, char columns
+        assert_eq!(tokens[6].span, Span { start : 19, end : 23, line : 2, column : 9 });
+        assert_eq!(tokens[7].span.column, 13);
+    }
+
+    #[test]
+    fn node_spans () {
+        let source = "print (1 + 2) * foo.bar(3);";
+        let mut s = Scanner::new(source.to_string());
+        let tokens = s.scan_tokens().0;
+        let stmts = Parser::new(tokens).parse().ok().unwrap();
+        let Stmt::Print(print) = &stmts[0] else { panic!("expected print") };
+        assert_eq!(&source[print.span.start..print.span.end], source);
+        let expr_span = print.expression.span();
+        assert_eq!(&source[expr_span.start..expr_span.end], "(1 + 2) * foo.bar(3)");
+    }
+
+    #[test]
+    fn render_human_snippet () {
+        let source = "var a = 1;\nprint a";
+        let mut lox = Lox::new();
+        let result = lox.run_source(source, Some("main.lox"));
+        let d = result.diagnostics.iter().next().unwrap();
+        let rendered = Renderer::new(source, false).render(d, ErrorFormat::Human);
+        assert_eq!(rendered, concat!(
+            "error[E0002]: Expect ';' after value\n",
+            " --> main.lox:2:8\n",
+            "  |\n",
+            "2 | print a\n",
+            "  |        ^ at end\n",
+            "  = help: insert ';' here\n",
+        ));
+    }
+
+    #[test]
+    fn render_underlines_whole_token () {
+        let source = "return nil;";
+        let mut lox = Lox::new();
+        let result = lox.run(source);
+        let d = result.diagnostics.iter().next().unwrap();
+        let rendered = Renderer::new(source, false).human(d);
+        assert!(rendered.contains("1 | return nil;\n  | ^^^^^^\n"), "{}", rendered);
+    }
+
+    #[test]
+    fn render_span_without_column () {
+        // tokens made outside the scanner carry `Span::default()`
+        let d = crate::error_handler::Diagnostic::error(ErrorCode::Runtime, Span::default(), "boom");
+        let rendered = Renderer::new("print 1;", false).human(&d);
+        assert!(rendered.contains("  | ^\n"), "{}", rendered);
+    }
+
+    #[test]
+    fn render_json () {
+        let mut lox = Lox::new();
+        let result = lox.run("print \"a\" - 1;");
+        let error = result.runtime_error.unwrap();
+        let json = render::json(&(&error).into());
+        assert_eq!(json, concat!(
+            "{\"severity\":\"error\",\"code\":\"E0004\",\"file\":null,\"line\":1,\"column\":11,",
+            "\"start\":10,\"end\":11,\"location\":\"\",\"message\":\"Operands must be numbers\",",
+            "\"notes\":[],\"help\":null}",
+        ));
+    }
+
+    #[test]
+    fn all_parse_errors_reported () {
+        let mut lox = Lox::new();
+        let result = lox.run("var = 2;\nprint (1;\nprint 2");
+        let messages : Vec<String> = result.diagnostics.iter().map(|d| d.to_string()).collect();
+        assert_eq!(messages, vec![
+            "[line 1] Error  at '=': Expect a variable name",
+            "[line 2] Error  at ';': Expect ')' after expression",
+            "[line 3] Error  at end: Expect ';' after value",
+        ]);
+    }
+
+    #[test]
+    fn half_parsed_program_does_not_run () {
+        let mut lox = Lox::new();
+        lox.run("var a = 1;\na = 2;\nprint ;");
+        let result = lox.run("a;");
+        assert_eq!(result.value.map(|v| v.to_string()), None);
+        assert_eq!(result.runtime_error.unwrap().message, "Undefined variable 'a'");
+    }
+
+    #[test]
+    fn scanner_collects_every_error () {
+        let mut s = Scanner::new("var a = @;\nvar b = #;".to_string());
+        let (_, errors) = s.scan_tokens();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].span.line, 1);
+        assert_eq!(errors[1].span.line, 2);
+    }
+
+    #[test]
+    fn parser_runs_after_scanner_errors () {
+        let mut lox = Lox::new();
+        let result = lox.run("var a = 1 # 2;\nprint ;");
+        let reported = result.diagnostics.iter().map(|d| (d.span.line, d.message.as_str())).collect::<Vec<_>>();
+        assert_eq!(reported, [(1, "Unexpected character"), (1, "Expect ';' after variable declaration"), (2, "Expect expression")]);
+        assert!(result.value.is_none() && result.runtime_error.is_none());
+    }
+
+    fn value_of (lox : &mut Lox, source : &str) -> String {
+        let result = lox.run(source);
+        assert!(result.is_ok(), "{:?}", result);
+        result.value.map(|v| v.to_string()).unwrap_or_default()
+    }
+
+    #[test]
+    fn catch_thrown_value () {
+        let mut lox = Lox::new();
+        lox.run("var got; try { throw 42; got = 0; } catch (e) { got = e; }");
+        assert_eq!(value_of(&mut lox, "got;"), "42");
+    }
+
+    #[test]
+    fn catch_native_runtime_error () {
+        let mut lox = Lox::new();
+        lox.run("var msg; var line;\ntry {\n  nil + 1;\n} catch (e) { msg = e.message; line = e.line; }");
+        assert_eq!(value_of(&mut lox, "msg;"), "Operands must be two numbers or two strings");
+        assert_eq!(value_of(&mut lox, "line;"), "3");
+    }
+
+    #[test]
+    fn finally_runs_on_return () {
+        let mut lox = Lox::new();
+        lox.run("var log = \"\"; fun f() { try { return 1; } finally { log = log + \"finally\"; } } var r = f();");
+        assert_eq!(value_of(&mut lox, "log;"), "finally");
+        assert_eq!(value_of(&mut lox, "r;"), "1");
+    }
+
+    #[test]
+    fn finally_runs_before_rethrow () {
+        let mut lox = Lox::new();
+        let result = lox.run("var log = 0; try { throw \"boom\"; } finally { log = 1; }");
+        assert_eq!(result.runtime_error.unwrap().message, "Uncaught exception: boom");
+        assert_eq!(value_of(&mut lox, "log;"), "1");
+    }
+
+    #[test]
+    fn try_needs_catch_or_finally () {
+        let mut lox = Lox::new();
+        let result = lox.run("try { print 1; }");
+        assert_eq!(result.diagnostics.iter().next().unwrap().message, "Expect 'catch' or 'finally' after try block");
+    }
+
+    #[test]
+    fn runtime_error_carries_stack_trace () {
+        let mut lox = Lox::new();
+        let result = lox.run("fun inner() {\n  return nil - 1;\n}\nfun outer() {\n  return inner();\n}\nouter();");
+        let error = result.runtime_error.unwrap();
+        let trace : Vec<(String, i32)> = error.trace.iter().map(|f| (f.function.clone(), f.call_site.line)).collect();
+        assert_eq!(trace, vec![("inner".to_string(), 5), ("outer".to_string(), 7)]);
+        assert!(lox.interpreter().call_stack.is_empty());
+    }
+
+    #[test]
+    fn uncaught_throw_carries_stack_trace () {
+        let mut lox = Lox::new();
+        let result = lox.run("fun fail() {\n  throw \"bad\";\n}\nfail();");
+        let error = result.runtime_error.unwrap();
+        assert_eq!(error.trace.len(), 1);
+        assert_eq!(error.trace[0].function, "fail");
+        let notes = crate::error_handler::Diagnostic::from(&error).notes;
+        assert_eq!(notes, vec!["in fail() called from line 4".to_string()]);
+    }
+
+    #[test]
+    fn list_literals_and_indexing () {
+        let mut lox = Lox::new();
+        lox.run("var xs = [1, \"two\", [3]]; xs[0] = xs[0] + 10;");
+        assert_eq!(value_of(&mut lox, "xs;"), "[11, \"two\", [3]]");
+        assert_eq!(value_of(&mut lox, "xs[2][0];"), "3");
+    }
+
+    #[test]
+    fn list_methods () {
+        let mut lox = Lox::new();
+        lox.run("var xs = []; xs.push(1); xs.push(2); xs.push(3); xs.insert(0, 0);");
+        assert_eq!(value_of(&mut lox, "xs.len();"), "4");
+        assert_eq!(value_of(&mut lox, "xs.pop();"), "3");
+        assert_eq!(value_of(&mut lox, "xs.slice(1, 3);"), "[1, 2]");
+        assert_eq!(value_of(&mut lox, "xs;"), "[0, 1, 2]");
+    }
+
+    #[test]
+    fn list_equality () {
+        let mut lox = Lox::new();
+        assert_eq!(value_of(&mut lox, "[1, [2, \"a\"]] == [1, [2, \"a\"]];"), "true");
+        assert_eq!(value_of(&mut lox, "[1, 2] != [1];"), "true");
+    }
+
+    #[test]
+    fn list_index_errors () {
+        let mut lox = Lox::new();
+        let result = lox.run("var xs = [1];\nxs[1];");
+        let error = result.runtime_error.unwrap();
+        assert_eq!(error.message, "Index 1 out of bounds");
+        assert_eq!(error.token.span.line, 2);
+        assert_eq!(lox.run("[].pop();").runtime_error.unwrap().message, "Cannot pop from an empty list");
+        assert_eq!(lox.run("xs[0.5];").runtime_error.unwrap().message, "Index must be an integer");
+    }
+
+    #[test]
+    fn map_literals_and_indexing () {
+        let mut lox = Lox::new();
+        lox.run("var m = {\"b\": 1, 2: \"two\", true: [3]}; m[\"a\"] = m[\"b\"] + 1; m[\"b\"] = 0;");
+        assert_eq!(value_of(&mut lox, "m;"), "{\"b\": 0, 2: \"two\", true: [3], \"a\": 2}");
+        assert_eq!(value_of(&mut lox, "m[true][0];"), "3");
+        assert_eq!(value_of(&mut lox, "({});"), "{}");
+    }
+
+    #[test]
+    fn map_methods () {
+        let mut lox = Lox::new();
+        lox.run("var m = {\"x\": 1, \"y\": 2, \"z\": 3};");
+        assert_eq!(value_of(&mut lox, "m.len();"), "3");
+        assert_eq!(value_of(&mut lox, "m.has(\"y\");"), "true");
+        assert_eq!(value_of(&mut lox, "m.remove(\"y\");"), "2");
+        assert_eq!(value_of(&mut lox, "m.has(\"y\");"), "false");
+        assert_eq!(value_of(&mut lox, "m.keys();"), "[\"x\", \"z\"]");
+        assert_eq!(value_of(&mut lox, "m.values();"), "[1, 3]");
+        assert_eq!(value_of(&mut lox, "m[\"z\"];"), "3");
+    }
+
+    #[test]
+    fn map_equality () {
+        let mut lox = Lox::new();
+        assert_eq!(value_of(&mut lox, "({\"a\": 1, \"b\": [2]} == {\"b\": [2], \"a\": 1});"), "true");
+        assert_eq!(value_of(&mut lox, "({1: 1} != {1: 2});"), "true");
+    }
+
+    #[test]
+    fn map_key_errors () {
+        let mut lox = Lox::new();
+        let result = lox.run("var m = {\"a\": 1};\nm[\"b\"];");
+        let error = result.runtime_error.unwrap();
+        assert_eq!(error.message, "Undefined key \"b\"");
+        assert_eq!(error.token.span.line, 2);
+        assert_eq!(lox.run("m[nil] = 1;").runtime_error.unwrap().message, "Map keys must be strings, numbers, booleans or objects");
+    }
+
+    #[test]
+    fn brace_statement_is_still_a_block () {
+        let mut lox = Lox::new();
+        assert!(lox.run("{ var a = 1; }").is_ok());
+        assert!(!lox.run("var m = {\"a\" 1};").is_ok());
+    }
+
+    #[test]
+    fn for_in_over_builtins () {
+        let mut lox = Lox::new();
+        assert_eq!(value_of(&mut lox, "var out = []; for (x in [1, 2, 3]) out.push(x * 2); out;"), "[2, 4, 6]");
+        assert_eq!(value_of(&mut lox, "out = []; for (k in {\"b\": 1, \"a\": 2}) out.push(k); out;"), "[\"b\", \"a\"]");
+        assert_eq!(value_of(&mut lox, "out = []; for (c in \"hey\") out.push(c); out;"), "[\"h\", \"e\", \"y\"]");
+        assert_eq!(value_of(&mut lox, "out = []; for (i in range(2, 5)) out.push(i); out;"), "[2, 3, 4]");
+    }
+
+    #[test]
+    fn for_in_over_iterable_instance () {
+        let mut lox = Lox::new();
+        let source = "
+            class Countdown {
+                init(n) { this.n = n; }
+                iterator() { return this; }
+                next() {
+                    if (this.n == 0) return nil;
+                    this.n = this.n - 1;
+                    return this.n + 1;
+                }
+            }
+            var out = [];
+            for (i in Countdown(3)) out.push(i);
+            out;";
+        assert_eq!(value_of(&mut lox, source), "[3, 2, 1]");
+    }
+
+    #[test]
+    fn for_in_closures_capture_each_value () {
+        let mut lox = Lox::new();
+        let source = "
+            var fs = [];
+            for (i in [0, 1, 2]) {
+                fun f() { return i; }
+                fs.push(f);
+            }
+            [fs[0](), fs[1](), fs[2]()];";
+        assert_eq!(value_of(&mut lox, source), "[0, 1, 2]");
+    }
+
+    #[test]
+    fn for_in_errors () {
+        let mut lox = Lox::new();
+        let error = lox.run("for (x in 1) print x;").runtime_error.unwrap();
+        assert_eq!(error.message, "Can only iterate over lists, maps, strings, ranges and iterable instances");
+        assert_eq!(error.token.lexeme, "in");
+        let error = lox.run("class A {}\nfor (x in A()) print x;").runtime_error.unwrap();
+        assert_eq!(error.message, "Instance has no 'iterator()' method");
+        assert_eq!(error.token.span.line, 2);
+    }
+
+    #[test]
+    fn lambda_expressions () {
+        let mut lox = Lox::new();
+        lox.run("var add = fun (a, b) { return a + b; }; var double = (a) => a * 2; var zero = () => 0;");
+        assert_eq!(value_of(&mut lox, "add(1, 2);"), "3");
+        assert_eq!(value_of(&mut lox, "double(4);"), "8");
+        assert_eq!(value_of(&mut lox, "zero();"), "0");
+        assert_eq!(value_of(&mut lox, "((x) => { var y = x + 1; return y; })(1);"), "2");
+        assert_eq!(value_of(&mut lox, "fun (x) { return -x; }(5);"), "-5");
+        assert_eq!(value_of(&mut lox, "add;"), "<fn: lambda>");
+        assert_eq!(value_of(&mut lox, "(1 + 2) * 3;"), "9");
+    }
+
+    #[test]
+    fn lambdas_capture_their_environment () {
+        let mut lox = Lox::new();
+        let source = "
+            fun counter() {
+                var n = 0;
+                return () => n = n + 1;
+            }
+            var c = counter();
+            c();
+            c();";
+        assert_eq!(value_of(&mut lox, source), "2");
+        assert_eq!(value_of(&mut lox, "var xs = [1, 2, 3]; var out = []; for (x in xs) out.push(((y) => y * x)(10)); out;"), "[10, 20, 30]");
+    }
+
+    #[test]
+    fn continue_runs_for_increment () {
+        let mut lox = Lox::new();
+        let source = "
+            var out = [];
+            for (var i = 0; i < 5; i = i + 1) {
+                if (i == 1 or i == 3) continue;
+                out.push(i);
+            }
+            out;";
+        assert_eq!(value_of(&mut lox, source), "[0, 2, 4]");
+    }
+
+    #[test]
+    fn break_and_continue_in_nested_loops () {
+        let mut lox = Lox::new();
+        let source = "
+            var out = [];
+            var i = 0;
+            while (i < 3) {
+                i = i + 1;
+                for (j in [0, 1, 2]) {
+                    if (j == 1) continue;
+                    if (j == 2) break;
+                    out.push([i, j]);
+                }
+                if (i == 2) { continue; }
+                out.push(i);
+            }
+            out;";
+        assert_eq!(value_of(&mut lox, source), "[[1, 0], 1, [2, 0], [3, 0], 3]");
+    }
+
+    #[test]
+    fn labeled_break_and_continue () {
+        let mut lox = Lox::new();
+        let source = "
+            var out = [];
+            outer: for (i in [0, 1, 2]) {
+                inner: for (j in [0, 1, 2]) {
+                    if (j == 1) continue outer;
+                    if (i == 2) break outer;
+                    out.push([i, j]);
+                }
+            }
+            out;";
+        assert_eq!(value_of(&mut lox, source), "[[0, 0], [1, 0]]");
+    }
+
+    #[test]
+    fn loop_control_errors () {
+        let mut lox = Lox::new();
+        let result = lox.run("outer: while (true) { break inner; }");
+        assert_eq!(result.diagnostics.iter().next().unwrap().message, "Undefined loop label 'inner'");
+        let result = lox.run("while (true) { fun f() { break; } }");
+        assert_eq!(result.diagnostics.iter().next().unwrap().message, "Cannot use 'break' outside of a loop");
+        let result = lox.run("outer: print 1;");
+        assert_eq!(result.diagnostics.iter().next().unwrap().message, "Expect loop after label");
+    }
+
+    #[test]
+    fn string_methods () {
+        let mut lox = Lox::new();
+        lox.run("var s = \"  Hello, wörld  \".trim();");
+        assert_eq!(value_of(&mut lox, "s.len();"), "12");
+        assert_eq!(value_of(&mut lox, "s.substring(7, 12);"), "wörld");
+        assert_eq!(value_of(&mut lox, "s.indexOf(\"r\");"), "9");
+        assert_eq!(value_of(&mut lox, "s.indexOf(\"xyz\");"), "-1");
+        assert_eq!(value_of(&mut lox, "s.split(\", \");"), "[\"Hello\", \"wörld\"]");
+        assert_eq!(value_of(&mut lox, "s.upper();"), "HELLO, WÖRLD");
+        assert_eq!(value_of(&mut lox, "s.lower();"), "hello, wörld");
+        assert_eq!(value_of(&mut lox, "s.replace(\"l\", \"L\");"), "HeLLo, wörLd");
+        assert_eq!(value_of(&mut lox, "s.startsWith(\"Hell\");"), "true");
+        assert_eq!(value_of(&mut lox, "\"ab\".chars();"), "[\"a\", \"b\"]");
+    }
+
+    #[test]
+    fn string_natives () {
+        let mut lox = Lox::new();
+        assert_eq!(value_of(&mut lox, "str(1.5) + str(true) + str([1]);"), "1.5true[1]");
+        assert_eq!(value_of(&mut lox, "num(\" 42 \") + 1;"), "43");
+        assert_eq!(value_of(&mut lox, "chr(65) + chr(ord(\"a\") + 1);"), "Ab");
+        let error = lox.run("num(\"abc\");").runtime_error.unwrap();
+        assert_eq!(error.message, "Cannot convert \"abc\" to a number");
+        let error = lox.run("\"abc\".substring(2, 1);").runtime_error.unwrap();
+        assert_eq!(error.message, "Substring start must not be after its end");
+        let error = lox.run("\"abc\".shout();").runtime_error.unwrap();
+        assert_eq!(error.message, "Undefined string method 'shout'");
+    }
+
+    #[test]
+    fn math_namespace () {
+        let mut lox = Lox::new();
+        assert_eq!(value_of(&mut lox, "Math.sqrt(16) + Math.pow(2, 10);"), "1028");
+        assert_eq!(value_of(&mut lox, "[Math.abs(-2), Math.floor(1.7), Math.ceil(1.2), Math.round(2.5)];"), "[2, 1, 2, 3]");
+        assert_eq!(value_of(&mut lox, "[Math.min(3, 4), Math.max(3, 4)];"), "[3, 4]");
+        assert_eq!(value_of(&mut lox, "[Math.sin(0), Math.cos(0), Math.tan(0), Math.log(1), Math.exp(0)];"), "[0, 1, 0, 0, 1]");
+        assert_eq!(value_of(&mut lox, "Math.round(Math.PI * 100) + Math.round(Math.E * 100);"), "586");
+    }
+
+    #[test]
+    fn math_random_is_seedable () {
+        let mut lox = Lox::new();
+        lox.run("Math.seed(42); var a = [Math.random(), Math.random()]; Math.seed(42);");
+        assert_eq!(value_of(&mut lox, "a == [Math.random(), Math.random()];"), "true");
+        assert_eq!(value_of(&mut lox, "var r = Math.random(); r >= 0 and r < 1;"), "true");
+    }
+
+    #[test]
+    fn math_random_is_unseeded_by_default () {
+        let draws = |mut random : Random| (0..4).map(|_| random.next_f64()).collect::<Vec<f64>>();
+        assert_ne!(draws(Random::default()), draws(Random::new(0)));
+    }
+
+    #[test]
+    fn math_argument_errors () {
+        let mut lox = Lox::new();
+        let error = lox.run("var x = 1;\nMath.sqrt(\"four\");").runtime_error.unwrap();
+        assert_eq!(error.message, "Math.sqrt expects a number but got \"four\"");
+        assert_eq!(error.token.span.line, 2);
+        assert_eq!(error.token.lexeme, ")");
+        assert!(lox.run("var sqrt = sqrt;").runtime_error.is_some());
+    }
+
+    #[test]
+    fn file_natives () {
+        let dir = std::env::temp_dir().join(format!("rlox-io-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut lox = Lox::new();
+        lox.interpreter().script_args = vec!["--fast".to_string()];
+        lox.run(&format!("var dir = {:?};", dir.to_string_lossy()));
+        lox.run("writeFile(dir + \"/b.txt\", \"one\"); appendFile(dir + \"/b.txt\", 2); writeFile(dir + \"/a.txt\", \"\");");
+        assert_eq!(value_of(&mut lox, "readFile(dir + \"/b.txt\");"), "one2");
+        assert_eq!(value_of(&mut lox, "listDir(dir);"), "[\"a.txt\", \"b.txt\"]");
+        assert_eq!(value_of(&mut lox, "[exists(dir + \"/a.txt\"), exists(dir + \"/c.txt\")];"), "[true, false]");
+        assert_eq!(value_of(&mut lox, "args();"), "[\"--fast\"]");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn io_errors_are_catchable () {
+        let mut lox = Lox::new();
+        let source = "
+            var message;
+            try { readFile(\"/definitely/not/here.txt\"); } catch (e) { message = e.message; }
+            message.startsWith(\"Cannot read '/definitely/not/here.txt'\");";
+        assert_eq!(value_of(&mut lox, source), "true");
+        let error = lox.run("listDir(42);").runtime_error.unwrap();
+        assert_eq!(error.message, "listDir() expects a path string but got 42");
+    }
+
+    #[test]
+    fn sandbox_disables_io () {
+        let mut lox = Lox::new();
+        lox.interpreter().allow_io = false;
+        let error = lox.run("exists(\".\");").runtime_error.unwrap();
+        assert_eq!(error.message, "exists() is not available in the sandbox");
+        assert_eq!(value_of(&mut lox, "var m; try { args(); } catch (e) { m = e.message; } m;"), "args() is not available in the sandbox");
+    }
+
+    #[test]
+    fn embedder_natives_capture_state () {
+        let calls = std::rc::Rc::new(std::cell::Cell::new(0));
+        let counter = std::rc::Rc::clone(&calls);
+        let mut lox = Lox::new();
+        lox.interpreter().define_native("tick", Arity::Exact(0), move |_interpreter, _args| {
+            counter.set(counter.get() + 1);
+            Ok(LiteralType::Number(counter.get() as f64))
+        });
+        assert_eq!(value_of(&mut lox, "tick(); tick();"), "2");
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn variadic_and_optional_natives () {
+        let mut lox = Lox::new();
+        lox.interpreter().define_native("sum", Arity::Variadic(0), |interpreter, args| {
+            let mut total = 0.0;
+            for arg in args {
+                match arg {
+                    LiteralType::Number(n) => total += n,
+                    _ => return Err(interpreter.native_error("sum() takes numbers")),
+                }
+            }
+            Ok(LiteralType::Number(total))
+        });
+        assert_eq!(value_of(&mut lox, "[sum(), sum(1), sum(1, 2, 3)];"), "[0, 1, 6]");
+        assert_eq!(value_of(&mut lox, "[Math.max(3, 9, 4), Math.min(2)];"), "[9, 2]");
+        assert_eq!(value_of(&mut lox, "var out = []; for (i in range(3)) out.push(i); out;"), "[0, 1, 2]");
+
+        let error = lox.run("var a = 1;\nsum(1, \"x\");").runtime_error.unwrap();
+        assert_eq!(error.message, "sum() takes numbers");
+        assert_eq!(error.token.span.line, 2);
+        assert_eq!(error.trace[0].function, "sum");
+        let error = lox.run("range(1, 2, 3);").runtime_error.unwrap();
+        assert_eq!(error.message, "Expected 1 to 2 arguments but got 3");
+        let error = lox.run("Math.max();").runtime_error.unwrap();
+        assert_eq!(error.message, "Expected at least 1 arguments but got 0");
+    }
+
+    // ? Regression suite for the natives: every registered native, global or in
+    // ? the Math namespace, is called from Lox source and must evaluate to true.
+    // ? Natives reading stdin are only checked through their sandbox error
+    #[test]
+    fn every_native_is_callable_from_lox () {
+        let dir = std::env::temp_dir().join(format!("rlox-natives-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("f.txt").to_string_lossy().to_string();
+
+        let cases : &[(&str, bool, &str)] = &[
+            ("clock", false, "clock() > 0;"),
+            ("range", false, "range(2, 4) == range(2, 4);"),
+            ("str", false, "str(12) == \"12\";"),
+            ("num", false, "num(\"1.5\") == 1.5;"),
+            ("chr", false, "chr(97) == \"a\";"),
+            ("ord", false, "ord(\"a\") == 97;"),
+            ("writeFile", false, "writeFile(file, \"a\") == nil;"),
+            ("appendFile", false, "appendFile(file, \"b\") == nil;"),
+            ("readFile", false, "readFile(file) == \"ab\";"),
+            ("exists", false, "exists(file);"),
+            ("listDir", false, "listDir(dir) == [\"f.txt\"];"),
+            ("args", false, "args() == [];"),
+            ("readLine", true, "var m; try { readLine(); } catch (e) { m = e.message; } m == \"readLine() is not available in the sandbox\";"),
+            ("input", true, "var m; try { input(\"> \"); } catch (e) { m = e.message; } m == \"input() is not available in the sandbox\";"),
+            ("Math.sqrt", false, "Math.sqrt(9) == 3;"),
+            ("Math.pow", false, "Math.pow(2, 3) == 8;"),
+            ("Math.abs", false, "Math.abs(-1) == 1;"),
+            ("Math.floor", false, "Math.floor(1.5) == 1;"),
+            ("Math.ceil", false, "Math.ceil(1.5) == 2;"),
+            ("Math.round", false, "Math.round(1.5) == 2;"),
+            ("Math.min", false, "Math.min(1, 2) == 1;"),
+            ("Math.max", false, "Math.max(1, 2) == 2;"),
+            ("Math.sin", false, "Math.sin(0) == 0;"),
+            ("Math.cos", false, "Math.cos(0) == 1;"),
+            ("Math.tan", false, "Math.tan(0) == 0;"),
+            ("Math.log", false, "Math.log(1) == 0;"),
+            ("Math.exp", false, "Math.exp(0) == 1;"),
+            ("Math.random", false, "Math.random() < 1;"),
+            ("Math.seed", false, "Math.seed(1) == nil;"),
+        ];
+
+        let mut registered = vec![];
+        let interpreter = Interpreter::new();
+        for (name, value) in interpreter.globals.borrow().values.iter() {
+            match value {
+                LiteralType::Callable(Callable::NativeFunction(_)) => registered.push(name.to_string()),
+                LiteralType::Callable(Callable::LoxInstance(math)) if *name == "Math" => {
+                    for (field, value) in math.borrow().fields() {
+                        if let LiteralType::Callable(Callable::NativeFunction(_)) = value {
+                            registered.push(format!("Math.{}", field));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        registered.sort();
+        let mut covered = cases.iter().map(|(name, _, _)| name.to_string()).collect::<Vec<String>>();
+        covered.sort();
+        assert_eq!(registered, covered, "every registered native needs a case here");
+
+        for (name, sandboxed, source) in cases {
+            let mut lox = Lox::new();
+            lox.interpreter().allow_io = !sandboxed;
+            lox.run(&format!("var dir = {:?}; var file = {:?};", dir.to_string_lossy(), file));
+            let result = lox.run(source);
+            assert!(result.is_ok(), "{}: {:?}", name, result.runtime_error);
+            assert_eq!(result.value.unwrap().to_string(), "true", "{}", name);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn native_arity_is_checked () {
+        let mut lox = Lox::new();
+        assert_eq!(lox.run("clock(1);").runtime_error.unwrap().message, "Expected 0 arguments but got 1");
+        assert_eq!(lox.run("\"a\".len(1);").runtime_error.unwrap().message, "Expected 0 arguments but got 1");
+        assert_eq!(lox.run("Math.pow(1);").runtime_error.unwrap().message, "Expected 2 arguments but got 1");
+    }
+
+    // * Writer the test keeps a handle to, to read back what the interpreter wrote
+    #[derive(Clone, Default)]
+    struct Captured (Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write (&mut self, buf : &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush (&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Captured {
+        fn text (&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    #[test]
+    fn print_goes_to_injected_output () {
+        let output = Captured::default();
+        let mut lox = Lox::with_io(Box::new(output.clone()), Box::new(std::io::empty()));
+        lox.run("print 1; print \"a\"; print [nil];");
+        assert_eq!(output.text(), "1\na\n[nil]\n");
+    }
+
+    #[test]
+    fn repl_echo_goes_to_injected_output () {
+        let output = Captured::default();
+        let mut lox = Lox::with_io(Box::new(output.clone()), Box::new(std::io::empty()));
+        lox.repl = true;
+        lox.run("var a = 2; a + 1;");
+        assert_eq!(output.text(), "3\n");
+    }
+
+    #[test]
+    fn read_natives_use_injected_input () {
+        let output = Captured::default();
+        let input = std::io::Cursor::new("first\r\nsecond\n");
+        let mut lox = Lox::with_io(Box::new(output.clone()), Box::new(input));
+        assert_eq!(value_of(&mut lox, "readLine();"), "first");
+        assert_eq!(value_of(&mut lox, "input(\"name? \");"), "second");
+        assert_eq!(value_of(&mut lox, "readLine();"), "nil");
+        assert_eq!(output.text(), "name? ");
+    }
+
+    fn expression_ids (source : &str, first : NodeId) -> (Vec<NodeId>, NodeId) {
+        let tokens = Scanner::new(source.to_string()).scan_tokens().0;
+        let mut parser = Parser::continuing(tokens, first);
+        let stmts = parser.parse().ok().unwrap();
+        let ids = stmts.iter().filter_map(|s| match s {
+            Stmt::Expression(e) => Some(e.expression.id()),
+            _ => None,
+        }).collect();
+        (ids, parser.next_id())
+    }
+
+    #[test]
+    fn node_ids_do_not_depend_on_earlier_parses () {
+        let (first, _) = expression_ids("a; b;", NodeId::default());
+        let (again, next) = expression_ids("a; b;", NodeId::default());
+        assert_eq!(first, again);
+        let (continued, _) = expression_ids("a; b;", next);
+        assert!(continued.iter().all(|id| *id >= next));
+    }
+
+    #[test]
+    fn resolutions_survive_later_repl_lines () {
+        let mut lox = Lox::new();
+        lox.repl = true;
+        // `x` sits one scope up from `g`; a later line reusing its id would
+        // overwrite that depth
+        lox.run("fun f() { var x = \"kept\"; fun g() { return x; } return g(); }");
+        lox.run("{ var y = 0; y; }");
+        assert_eq!(value_of(&mut lox, "f();"), "kept");
+    }
+
+    #[test]
+    fn interpreters_parse_in_parallel () {
+        let results = std::thread::scope(|scope| {
+            let workers = (0..4).map(|n| scope.spawn(move || {
+                let mut lox = Lox::new();
+                let source = format!("fun f(x) {{ var y = x; return y * {}; }} f(3);", n);
+                value_of(&mut lox, &source)
+            })).collect::<Vec<_>>();
+            workers.into_iter().map(|w| w.join().unwrap()).collect::<Vec<String>>()
+        });
+        assert_eq!(results, vec!["0", "3", "6", "9"]);
+    }
+
+    fn vm () -> Lox {
+        let mut lox = Lox::new();
+        lox.backend = Backend::Vm;
+        lox
+    }
+
+    #[test]
+    fn vm_closures_share_captured_variables () {
+        let mut lox = vm();
+        lox.run("fun pair() { var n = 0; fun inc() { n = n + 1; } fun get() { return n; } return [inc, get]; }");
+        lox.run("var p = pair(); p[0](); p[0]();");
+        assert_eq!(value_of(&mut lox, "p[1]();"), "2");
+        // each loop iteration closes over its own variable
+        lox.run("var fs = []; for (x in [1, 2, 3]) { fs.push(fun () { return x; }); }");
+        assert_eq!(value_of(&mut lox, "fs[0]() + fs[2]();"), "4");
+    }
+
+    #[test]
+    fn vm_runs_finally_on_every_exit () {
+        let output = Captured::default();
+        let mut lox = Lox::with_io(Box::new(output.clone()), Box::new(std::io::empty()));
+        lox.backend = Backend::Vm;
+        lox.run("fun f() { while (true) { try { return 1; } finally { print \"return\"; } } }");
+        assert_eq!(value_of(&mut lox, "f();"), "1");
+        lox.run("while (true) { try { break; } finally { print \"break\"; } }");
+        let result = lox.run("try { throw \"up\"; } finally { print \"throw\"; }");
+        assert_eq!(result.runtime_error.map(|e| e.message), Some("Uncaught exception: up".to_string()));
+        assert_eq!(output.text(), "return\nbreak\nthrow\n");
+    }
+
+    #[test]
+    fn vm_keeps_state_between_repl_lines () {
+        let output = Captured::default();
+        let mut lox = Lox::with_io(Box::new(output.clone()), Box::new(std::io::empty()));
+        lox.backend = Backend::Vm;
+        lox.repl = true;
+        lox.run("class Counter { init() { this.n = 0; } bump() { this.n = this.n + 1; return this; } }");
+        lox.run("var c = Counter();");
+        assert_eq!(value_of(&mut lox, "c.bump().bump().n;"), "2");
+        // a runtime error leaves the VM usable
+        assert!(lox.run("c.missing;").runtime_error.is_some());
+        assert_eq!(value_of(&mut lox, "c.n;"), "2");
+        assert_eq!(output.text(), "2\n2\n");
+    }
+
+    #[test]
+    fn resolver_numbers_slots_in_declaration_order () {
+        let mut lox = Lox::new();
+        lox.run("fun f(a, b) { var c = a; { var d = c; return b + d; } }");
+        let mut resolved = lox.interpreter().locals.values().copied().collect::<Vec<(usize, usize)>>();
+        resolved.sort();
+        // `a` from the body, then `d`, `b` and `c` from the block
+        assert_eq!(resolved, vec![(0, 0), (0, 0), (1, 1), (1, 2)]);
+        assert_eq!(value_of(&mut lox, "f(1, 2);"), "3");
+    }
+
+    #[test]
+    fn symbols_are_interned () {
+        let a = Symbol::intern("counter");
+        let b = Symbol::intern(&String::from("counter"));
+        assert_eq!(a, b);
+        assert!(std::ptr::eq(a.as_str(), b.as_str()));
+        assert_ne!(a, Symbol::intern("Counter"));
+        assert_eq!(a, "counter");
+
+        let tokens = Scanner::new("counter = counter;".to_string()).scan_tokens().0;
+        assert!(std::ptr::eq(tokens[0].lexeme.as_str(), tokens[2].lexeme.as_str()));
+    }
+
+    #[test]
+    fn literal_lexemes_are_not_interned () {
+        let tokens = Scanner::new("\"text\" 12 \"text\" 12;".to_string()).scan_tokens().0;
+        assert_eq!(tokens[0].lexeme, "\"text\"");
+        assert!(!std::ptr::eq(tokens[0].lexeme.as_str(), tokens[2].lexeme.as_str()));
+        assert!(!std::ptr::eq(tokens[1].lexeme.as_str(), tokens[3].lexeme.as_str()));
+    }
+
+    #[test]
+    fn string_values_share_their_text () {
+        let mut lox = Lox::new();
+        lox.run("var s = \"shared\"; var t = s; var xs = [s];");
+        let globals = Rc::clone(&lox.interpreter().globals);
+        let text = |name : &str| match globals.borrow().values.get(&Symbol::intern(name)) {
+            Some(LiteralType::String(s)) => Rc::clone(s),
+            Some(LiteralType::List(xs)) => match &xs.borrow()[0] {
+                LiteralType::String(s) => Rc::clone(s),
+                other => panic!("{:?}", other),
+            },
+            other => panic!("{:?}", other),
+        };
+        assert!(Rc::ptr_eq(&text("s"), &text("t")));
+        assert!(Rc::ptr_eq(&text("s"), &text("xs")));
+    }
+
+    #[test]
+    fn methods_see_the_instance_they_were_taken_from () {
+        let mut lox = Lox::new();
+        lox.run("class A { me() { return this; } } var a = A(); var me = a.me; var b = me();");
+        let globals = Rc::clone(&lox.interpreter().globals);
+        let instance = |name : &str| match globals.borrow().values.get(&Symbol::intern(name)) {
+            Some(LiteralType::Callable(Callable::LoxInstance(instance))) => Rc::clone(instance),
+            other => panic!("{:?}", other),
+        };
+        assert!(Rc::ptr_eq(&instance("a"), &instance("b")));
+    }
+
+    #[test]
+    fn bound_methods_are_equal_per_receiver_on_both_backends () {
+        for backend in [Backend::TreeWalk, Backend::Vm] {
+            let mut lox = Lox::new();
+            lox.backend = backend;
+            lox.run("class A { m() {} n() {} } var a = A(); var m = a.m; var xs = [];");
+            assert_eq!(value_of(&mut lox, "m == m;"), "true");
+            assert_eq!(value_of(&mut lox, "a.m == a.m;"), "true");
+            assert_eq!(value_of(&mut lox, "[a.m == A().m, a.m == a.n];"), "[false, false]");
+            assert_eq!(value_of(&mut lox, "A.m == A.m;"), "true");
+            assert_eq!(value_of(&mut lox, "[xs.push == xs.push, xs.push == [].push];"), "[true, false]");
+            assert_eq!(value_of(&mut lox, "var keys = {a.m: 1}; keys[a.m];"), "1");
+        }
+    }
+
+    #[test]
+    fn errors_in_equals_and_hash_unwind_on_both_backends () {
+        for backend in [Backend::TreeWalk, Backend::Vm] {
+            let mut lox = Lox::new();
+            lox.backend = backend;
+            lox.run("class P { equals(o) { throw \"eq\"; } hash() { return [1]; } }");
+            assert_eq!(value_of(&mut lox, "var got; try { P() == 1; } catch (e) { got = e; } got;"), "eq");
+            let message = lox.run("({})[P()] = 1;").runtime_error.map(|e| e.message);
+            assert_eq!(message.as_deref(), Some("'hash()' must return a string, number or boolean"));
+            let message = lox.run("({}).has(P());").runtime_error.map(|e| e.message);
+            assert_eq!(message.as_deref(), Some("'hash()' must return a string, number or boolean"));
+        }
+    }
+}
@@ -0,0 +1,771 @@
+// ? Stack VM running the `Compiler`'s bytecode; the `--backend=vm`
+// ? alternative to the tree-walking `Interpreter`. Values, natives and
+// ? globals are shared with the interpreter, which the VM keeps as its host:
+// ? natives run against it, and output goes through its streams
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::rc::Rc;
+
+use crate::chunk::{Op, Proto};
+use crate::compiler::Script;
+use crate::error_handler::{RuntimeError, TraceFrame};
+use crate::interpreter::{self, Exit, Interpreter, Thrown};
+use crate::iter::LoxIter;
+use crate::loxcallable::{Arity, Callable, LoxCallable};
+use crate::map::{self, LoxMap, MapKey};
+use crate::scanner::{LiteralType, Span, Token, TokenType};
+use crate::symbol::Symbol;
+
+// ? A variable captured by a closure: still in its stack slot while the
+// ? declaring frame runs, moved into the upvalue once that slot goes away
+#[derive(Debug)]
+pub enum Upvalue {
+    Open (usize),
+    Closed (LiteralType),
+}
+
+pub struct Closure {
+    pub function : Rc<Proto>,
+    pub upvalues : Vec<Rc<RefCell<Upvalue>>>,
+}
+
+pub struct VmClass {
+    pub name : String,
+    pub methods : RefCell<HashMap<Symbol, Rc<Closure>>>,
+}
+
+pub struct VmInstance {
+    pub class : Rc<VmClass>,
+    pub fields : HashMap<Symbol, LiteralType>,
+}
+
+pub struct BoundMethod {
+    pub receiver : LiteralType,
+    pub method : Rc<Closure>,
+}
+
+impl VmClass {
+    pub fn find_method (&self, name : &Symbol) -> Option<Rc<Closure>> {
+        self.methods.borrow().get(name).cloned()
+    }
+}
+
+// closures and instances can reach themselves, so Debug stays shallow
+impl Debug for Closure {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Closure({})", self.function.name)
+    }
+}
+
+impl Debug for VmClass {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "VmClass({})", self.name)
+    }
+}
+
+impl Debug for VmInstance {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "VmInstance({})", self.class.name)
+    }
+}
+
+impl Debug for BoundMethod {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "BoundMethod({})", self.method.function.name)
+    }
+}
+
+impl Display for Closure {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "<fn: {}>", self.function.name)
+    }
+}
+
+impl Display for VmClass {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "<class: {}>", self.name)
+    }
+}
+
+impl Display for VmInstance {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "<class: {} instance>", self.class.name)
+    }
+}
+
+impl Display for BoundMethod {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.method)
+    }
+}
+
+struct Frame {
+    closure : Rc<Closure>,
+    ip : usize,
+    // stack index of slot 0
+    base : usize,
+    // what the iterator stack held when the call started
+    iterators : usize,
+    call_site : Span,
+}
+
+// ? An active try block: an error unwinds the frames, stack and iterators
+// ? back to what they were when it was pushed and jumps to `target`
+struct Handler {
+    frames : usize,
+    stack : usize,
+    iterators : usize,
+    set_aside : usize,
+    target : usize,
+    rethrow : bool,
+}
+
+enum VmIter {
+    // lists, maps, strings and ranges, walked like the interpreter does
+    Host (LoxIter),
+    // the object an instance's `iterator()` returned
+    Instance (Rc<RefCell<VmInstance>>),
+}
+
+// * Where the stacks stood before a call made from Rust, to restore on error
+struct Mark {
+    stack : usize,
+    frames : usize,
+    iterators : usize,
+    set_aside : usize,
+}
+
+#[derive(Default)]
+pub struct Vm {
+    stack : Vec<LiteralType>,
+    frames : Vec<Frame>,
+    handlers : Vec<Handler>,
+    iterators : Vec<VmIter>,
+    // errors kept by finally-only handlers until `Rethrow`
+    set_aside : Vec<Exit>,
+    // upvalues still pointing into the stack
+    open_upvalues : Vec<Rc<RefCell<Upvalue>>>,
+}
+
+// * Token standing in for the source position an instruction came from
+fn token (span : Span, lexeme : &str) -> Token {
+    Token {
+        token_type : TokenType::Identifier,
+        lexeme : Symbol::intern(lexeme),
+        literal : LiteralType::Nil,
+        span,
+    }
+}
+
+impl Vm {
+    pub fn new () -> Vm {
+        Vm::default()
+    }
+
+    // * Runs a compiled program; like `Interpreter::interpret`, the value is
+    // * the one of the trailing expression statement
+    pub fn run (&mut self, host : &mut Interpreter, script : Script) -> Result<Option<LiteralType>, Exit> {
+        let closure = Rc::new(Closure {
+            function : script.function,
+            upvalues : vec![],
+        });
+        let mark = self.mark();
+        self.stack.push(LiteralType::Callable(Callable::VmClosure(Rc::clone(&closure))));
+        self.frames.push(Frame {
+            closure,
+            ip : 0,
+            base : 0,
+            iterators : 0,
+            call_site : Span::default(),
+        });
+        match self.execute(host, mark.frames) {
+            Ok(value) => Ok(script.has_value.then_some(value)),
+            Err(e) => {
+                self.reset(&mark);
+                Err(e)
+            }
+        }
+    }
+
+    fn mark (&self) -> Mark {
+        Mark {
+            stack : self.stack.len(),
+            frames : self.frames.len(),
+            iterators : self.iterators.len(),
+            set_aside : self.set_aside.len(),
+        }
+    }
+
+    fn reset (&mut self, mark : &Mark) {
+        self.close_upvalues(mark.stack);
+        self.stack.truncate(mark.stack);
+        self.frames.truncate(mark.frames);
+        self.handlers.retain(|h| h.frames <= mark.frames);
+        self.iterators.truncate(mark.iterators);
+        self.set_aside.truncate(mark.set_aside);
+    }
+
+    // * Runs until the frame count drops back to `depth`, returning what the
+    // * last frame returned. Errors unwind to handlers pushed above `depth`
+    fn execute (&mut self, host : &mut Interpreter, depth : usize) -> Result<LiteralType, Exit> {
+        loop {
+            match self.step(host, depth) {
+                Ok(Some(value)) => return Ok(value),
+                Ok(None) => {}
+                Err(e) => self.unwind(host, e, depth)?,
+            }
+        }
+    }
+
+    // * Jumps to the innermost handler pushed above `depth`, or hands the error back
+    fn unwind (&mut self, host : &mut Interpreter, error : Exit, depth : usize) -> Result<(), Exit> {
+        let Some(handler) = self.handlers.last() else { return Err(error) };
+        if handler.frames <= depth {
+            return Err(error);
+        }
+        let handler = self.handlers.pop().unwrap();
+        self.frames.truncate(handler.frames);
+        self.close_upvalues(handler.stack);
+        self.stack.truncate(handler.stack);
+        self.iterators.truncate(handler.iterators);
+        self.set_aside.truncate(handler.set_aside);
+        self.frames.last_mut().unwrap().ip = handler.target;
+
+        if handler.rethrow {
+            self.set_aside.push(error);
+            return Ok(());
+        }
+        let value = match error {
+            Exit::Throw(thrown) => thrown.value,
+            Exit::RuntimeError(e) => host.error_value(&e),
+            Exit::Return(_) | Exit::Break(_) | Exit::Continue(_) => unreachable!("compiled to jumps"),
+        };
+        self.stack.push(value);
+        Ok(())
+    }
+
+    // * Active calls, innermost first, as the interpreter records them
+    fn trace (&self) -> Vec<TraceFrame> {
+        self.frames.iter().skip(1).rev().map(|frame| TraceFrame {
+            function : frame.closure.function.name.clone(),
+            call_site : frame.call_site,
+        }).collect()
+    }
+
+    fn error (&self, span : Span, message : impl Into<String>) -> Exit {
+        Exit::RuntimeError(Box::new(RuntimeError {
+            token : token(span, ""),
+            message : message.into(),
+            trace : self.trace(),
+        }))
+    }
+
+    fn pop (&mut self) -> LiteralType {
+        self.stack.pop().expect("stack underflow")
+    }
+
+    fn peek (&self, distance : usize) -> &LiteralType {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    fn capture_upvalue (&mut self, slot : usize) -> Rc<RefCell<Upvalue>> {
+        for upvalue in &self.open_upvalues {
+            if let Upvalue::Open(s) = *upvalue.borrow() {
+                if s == slot {
+                    return Rc::clone(upvalue);
+                }
+            }
+        }
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.push(Rc::clone(&upvalue));
+        upvalue
+    }
+
+    // * Moves every variable captured from slot `from` upwards off the stack
+    fn close_upvalues (&mut self, from : usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let slot = match *upvalue.borrow() {
+                Upvalue::Open(slot) => slot,
+                Upvalue::Closed(_) => return false,
+            };
+            if slot < from {
+                return true;
+            }
+            *upvalue.borrow_mut() = Upvalue::Closed(stack[slot].clone());
+            false
+        });
+    }
+
+    fn read_upvalue (&self, upvalue : &Rc<RefCell<Upvalue>>) -> LiteralType {
+        match &*upvalue.borrow() {
+            Upvalue::Open(slot) => self.stack[*slot].clone(),
+            Upvalue::Closed(value) => value.clone(),
+        }
+    }
+
+    fn write_upvalue (&mut self, upvalue : &Rc<RefCell<Upvalue>>, value : LiteralType) {
+        let slot = match &mut *upvalue.borrow_mut() {
+            Upvalue::Open(slot) => *slot,
+            Upvalue::Closed(v) => {
+                *v = value;
+                return;
+            }
+        };
+        self.stack[slot] = value;
+    }
+
+    // * Calls the value `argc` slots below the top with the arguments above it.
+    // * Lox functions get a new frame; natives and classes without `init`
+    // * complete right away
+    fn call_value (&mut self, host : &mut Interpreter, argc : usize, span : Span) -> Result<(), Exit> {
+        let base = self.stack.len() - 1 - argc;
+        let callee = self.stack[base].clone();
+        let LiteralType::Callable(callable) = callee else {
+            return Err(self.error(span, "Can only call functions and classes"));
+        };
+        match callable {
+            Callable::VmClosure(closure) => self.call_closure(closure, argc, span),
+            Callable::VmBoundMethod(bound) => {
+                self.stack[base] = bound.receiver.clone();
+                self.call_closure(Rc::clone(&bound.method), argc, span)
+            }
+            Callable::VmClass(class) => {
+                let instance = Rc::new(RefCell::new(VmInstance {
+                    class : Rc::clone(&class),
+                    fields : HashMap::new(),
+                }));
+                self.stack[base] = LiteralType::Callable(Callable::VmInstance(instance));
+                match class.find_method(&Symbol::intern("init")) {
+                    Some(init) => self.call_closure(init, argc, span),
+                    None if argc != 0 => Err(self.error(span, format!("Expected 0 arguments but got {}", argc))),
+                    None => Ok(()),
+                }
+            }
+            Callable::NativeFunction(native) => self.call_native(host, &native, argc, span),
+            Callable::NativeMethod(method) => {
+                // map methods cannot call `hash()` themselves, so an instance
+                // key is worked out here and handed to the key method
+                if let (LiteralType::Map(map), Some(by_key), 1) = (&*method.receiver, map::key_method(&method.name), argc) {
+                    let arg = self.stack[base + 1].clone();
+                    if let Some(key) = self.hashed_key(host, map, &arg, span)? {
+                        self.stack.truncate(base);
+                        self.stack.push(by_key(map, &key));
+                        return Ok(());
+                    }
+                }
+                self.call_native(host, &method, argc, span)
+            }
+            _ => Err(self.error(span, "Can only call functions and classes")),
+        }
+    }
+
+    fn call_closure (&mut self, closure : Rc<Closure>, argc : usize, span : Span) -> Result<(), Exit> {
+        if closure.function.arity != argc {
+            return Err(self.error(span, format!("Expected {} arguments but got {}", Arity::Exact(closure.function.arity), argc)));
+        }
+        if self.frames.len() >= interpreter::FRAMES_MAX {
+            return Err(self.error(span, "Stack overflow"));
+        }
+        self.frames.push(Frame {
+            closure,
+            ip : 0,
+            base : self.stack.len() - 1 - argc,
+            iterators : self.iterators.len(),
+            call_site : span,
+        });
+        Ok(())
+    }
+
+    fn call_native (&mut self, host : &mut Interpreter, native : &dyn LoxCallable, argc : usize, span : Span) -> Result<(), Exit> {
+        if !native.arity().accepts(argc) {
+            return Err(self.error(span, format!("Expected {} arguments but got {}", native.arity(), argc)));
+        }
+        let args = self.stack.split_off(self.stack.len() - argc);
+        self.pop();
+
+        let enclosing_site = host.native_call_site.replace(token(span, ""));
+        let mut result = native.call(host, &args);
+        host.native_call_site = enclosing_site;
+
+        // the native shows up in the trace like any other call
+        if let Err(Exit::RuntimeError(e)) = &mut result {
+            if e.trace.is_empty() {
+                e.trace.push(TraceFrame {
+                    function : native.name(),
+                    call_site : span,
+                });
+                e.trace.extend(self.trace());
+            }
+        }
+        self.stack.push(result?);
+        Ok(())
+    }
+
+    // * Calls the method `name` on `instance` from Rust, None if the class has
+    // * no such method
+    fn call_method (&mut self, host : &mut Interpreter, instance : &Rc<RefCell<VmInstance>>, name : &str, args : Vec<LiteralType>, span : Span) -> Result<Option<LiteralType>, Exit> {
+        let Some(method) = instance.borrow().class.find_method(&Symbol::intern(name)) else { return Ok(None) };
+        if method.function.arity != args.len() {
+            return Err(self.error(span, format!("Expected '{}' to take {} arguments but it takes {}", name, args.len(), method.function.arity)));
+        }
+        let mark = self.mark();
+        let argc = args.len();
+        self.stack.push(LiteralType::Callable(Callable::VmInstance(Rc::clone(instance))));
+        self.stack.extend(args);
+        self.call_closure(method, argc, span)?;
+        match self.execute(host, mark.frames) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) => {
+                self.reset(&mark);
+                Err(e)
+            }
+        }
+    }
+
+    // * `left == right` decided by an `equals(other)` method, asking the left
+    // * operand first; None when neither is an instance with one
+    fn user_equals (&mut self, host : &mut Interpreter, left : &LiteralType, right : &LiteralType, span : Span) -> Result<Option<bool>, Exit> {
+        for (object, other) in [(left, right), (right, left)] {
+            if let LiteralType::Callable(Callable::VmInstance(instance)) = object {
+                if let Some(result) = self.call_method(host, instance, "equals", vec![other.clone()], span)? {
+                    return Ok(Some(interpreter::is_truthy(&result)));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // * Key in `map` of an instance with a `hash()` method: the stored key it
+    // * `equals`, or a new one. None for any other value
+    fn hashed_key (&mut self, host : &mut Interpreter, map : &Rc<RefCell<LoxMap>>, value : &LiteralType, span : Span) -> Result<Option<MapKey>, Exit> {
+        let LiteralType::Callable(object @ Callable::VmInstance(instance)) = value else {
+            return Ok(None);
+        };
+        match self.call_method(host, instance, "hash", vec![], span)? {
+            Some(hash) => {
+                let key = MapKey::hashed(object, &hash).map_err(|message| self.error(span, message))?;
+                // `equals` may touch the map, so it isn't borrowed meanwhile
+                let colliding = map.borrow().colliding(&key);
+                for stored in colliding {
+                    if stored == key || self.user_equals(host, value, &stored.to_value(), span)? == Some(true) {
+                        return Ok(Some(stored));
+                    }
+                }
+                Ok(Some(key))
+            }
+            None if instance.borrow().class.find_method(&Symbol::intern("equals")).is_some() => Err(self.error(span, interpreter::UNHASHABLE)),
+            None => Ok(None),
+        }
+    }
+
+    fn get_property (&mut self, object : LiteralType, name : &Symbol, span : Span) -> Result<LiteralType, Exit> {
+        if let Some(method) = interpreter::value_method(&object, name) {
+            return method.map_err(|message| self.error(span, message));
+        }
+        match &object {
+            LiteralType::Callable(Callable::VmInstance(instance)) => {
+                if let Some(value) = instance.borrow().fields.get(name) {
+                    return Ok(value.clone());
+                }
+                match instance.borrow().class.find_method(name) {
+                    Some(method) => Ok(LiteralType::Callable(Callable::VmBoundMethod(Rc::new(BoundMethod {
+                        receiver : object.clone(),
+                        method,
+                    })))),
+                    None => Err(self.error(span, format!("Undefined property '{}'", name))),
+                }
+            }
+            LiteralType::Callable(Callable::LoxInstance(instance)) => {
+                instance.borrow().get(&token(span, name)).map_err(|e| match e {
+                    Exit::RuntimeError(e) => self.error(span, e.message),
+                    e => e,
+                })
+            }
+            LiteralType::Callable(Callable::VmClass(class)) => match class.find_method(name) {
+                Some(method) => Ok(LiteralType::Callable(Callable::VmClosure(method))),
+                None => Err(self.error(span, format!("Undefined static methods '{}' on class <{}>", name, class.name))),
+            },
+            _ => Err(self.error(span, "Only instances have properties")),
+        }
+    }
+
+    fn iterator (&mut self, host : &mut Interpreter, iterable : LiteralType, span : Span) -> Result<VmIter, Exit> {
+        let LiteralType::Callable(Callable::VmInstance(instance)) = iterable else {
+            return LoxIter::new(host, iterable, &token(span, "in")).map(VmIter::Host).map_err(|e| self.traced(e));
+        };
+        match self.call_method(host, &instance, "iterator", vec![], span)? {
+            Some(LiteralType::Callable(Callable::VmInstance(iterator))) => Ok(VmIter::Instance(iterator)),
+            Some(_) => Err(self.error(span, "'iterator()' must return an instance")),
+            None => Err(self.error(span, "Instance has no 'iterator()' method")),
+        }
+    }
+
+    fn next (&mut self, host : &mut Interpreter, span : Span) -> Result<Option<LiteralType>, Exit> {
+        let iterator = match self.iterators.last_mut().unwrap() {
+            VmIter::Host(iter) => return iter.next(host, &token(span, "in")).map_err(|e| self.traced(e)),
+            VmIter::Instance(iterator) => Rc::clone(iterator),
+        };
+        match self.call_method(host, &iterator, "next", vec![], span)? {
+            Some(LiteralType::Nil) => Ok(None),
+            Some(value) => Ok(Some(value)),
+            None => Err(self.error(span, "Iterator has no 'next()' method")),
+        }
+    }
+
+    // * Fills in the trace of an error raised outside any VM call
+    fn traced (&self, mut error : Exit) -> Exit {
+        if let Exit::RuntimeError(e) = &mut error {
+            if e.trace.is_empty() {
+                e.trace = self.trace();
+            }
+        }
+        error
+    }
+
+    // * Runs one instruction; Some once the frame at `depth` has returned
+    fn step (&mut self, host : &mut Interpreter, depth : usize) -> Result<Option<LiteralType>, Exit> {
+        let frame = self.frames.last_mut().unwrap();
+        let closure = Rc::clone(&frame.closure);
+        let chunk = &closure.function.chunk;
+        let ip = frame.ip;
+        let base = frame.base;
+        frame.ip += 1;
+        let span = chunk.spans[ip];
+
+        match &chunk.code[ip] {
+            Op::Constant(index) => self.stack.push(chunk.constants[*index].clone()),
+            Op::Nil => self.stack.push(LiteralType::Nil),
+            Op::True => self.stack.push(LiteralType::Bool(true)),
+            Op::False => self.stack.push(LiteralType::Bool(false)),
+            Op::Pop => {
+                self.pop();
+            }
+
+            Op::GetLocal(slot) => self.stack.push(self.stack[base + slot].clone()),
+            Op::SetLocal(slot) => self.stack[base + slot] = self.peek(0).clone(),
+            Op::GetUpvalue(index) => self.stack.push(self.read_upvalue(&closure.upvalues[*index])),
+            Op::SetUpvalue(index) => self.write_upvalue(&closure.upvalues[*index], self.peek(0).clone()),
+            Op::GetGlobal(index) => {
+                let name = &chunk.names[*index];
+                let value = host.globals.borrow().values.get(name).cloned();
+                match value {
+                    Some(value) => self.stack.push(value),
+                    None => return Err(self.error(span, format!("Undefined variable '{}'", name))),
+                }
+            }
+            Op::DefineGlobal(index) => {
+                let value = self.pop();
+                host.globals.borrow_mut().define(chunk.names[*index].clone(), value);
+            }
+            // assigning an undeclared global declares it, as in the interpreter
+            Op::SetGlobal(index) => host.globals.borrow_mut().define(chunk.names[*index].clone(), self.peek(0).clone()),
+
+            Op::GetProperty(index) => {
+                let object = self.pop();
+                let value = self.get_property(object, &chunk.names[*index], span)?;
+                self.stack.push(value);
+            }
+            Op::SetProperty(index) => {
+                let value = self.pop();
+                let object = self.pop();
+                let name = &chunk.names[*index];
+                match &object {
+                    LiteralType::Callable(Callable::VmInstance(instance)) => {
+                        instance.borrow_mut().fields.insert(name.clone(), value.clone());
+                    }
+                    LiteralType::Callable(Callable::LoxInstance(instance)) => {
+                        instance.borrow_mut().set(name, value.clone()).map_err(|message| self.error(span, message))?;
+                    }
+                    _ => return Err(self.error(span, "Only instances have fields")),
+                }
+                self.stack.push(value);
+            }
+            Op::GetSuper(index) => {
+                let super_class = self.pop();
+                let receiver = self.pop();
+                let LiteralType::Callable(Callable::VmClass(super_class)) = super_class else {
+                    return Err(self.error(span, "Undefined property"));
+                };
+                match super_class.find_method(&chunk.names[*index]) {
+                    Some(method) => self.stack.push(LiteralType::Callable(Callable::VmBoundMethod(Rc::new(BoundMethod {
+                        receiver,
+                        method,
+                    })))),
+                    None => return Err(self.error(span, "Undefined property")),
+                }
+            }
+            Op::Index => {
+                let key = self.pop();
+                let object = self.pop();
+                let hashed = match &object {
+                    LiteralType::Map(map) => self.hashed_key(host, map, &key, span)?,
+                    _ => None,
+                };
+                let value = interpreter::get_index(&object, &key, hashed).map_err(|message| self.error(span, message))?;
+                self.stack.push(value);
+            }
+            Op::SetIndex => {
+                let value = self.pop();
+                let key = self.pop();
+                let object = self.pop();
+                let hashed = match &object {
+                    LiteralType::Map(map) => self.hashed_key(host, map, &key, span)?,
+                    _ => None,
+                };
+                interpreter::set_index(&object, &key, hashed, value.clone()).map_err(|message| self.error(span, message))?;
+                self.stack.push(value);
+            }
+
+            Op::Binary(operator) => {
+                let right = self.pop();
+                let left = self.pop();
+                let equal = match operator {
+                    TokenType::EqualEqual | TokenType::BangEqual => self.user_equals(host, &left, &right, span)?,
+                    _ => None,
+                };
+                let value = match equal {
+                    Some(equal) => LiteralType::Bool(equal == (*operator == TokenType::EqualEqual)),
+                    None => interpreter::binary_op(operator, left, right).map_err(|message| self.error(span, message))?,
+                };
+                self.stack.push(value);
+            }
+            Op::Not => {
+                let value = self.pop();
+                self.stack.push(LiteralType::Bool(!interpreter::is_truthy(&value)));
+            }
+            Op::Negate => {
+                let value = self.pop();
+                let value = interpreter::unary_op(&TokenType::Minus, value).map_err(|message| self.error(span, message))?;
+                self.stack.push(value);
+            }
+
+            Op::Print => {
+                let value = self.pop();
+                host.print_val(&value);
+            }
+            Op::Echo => host.print_val(self.peek(0)),
+
+            Op::Jump(target) => self.frames.last_mut().unwrap().ip = *target,
+            Op::JumpIfFalse(target) => {
+                if !interpreter::is_truthy(self.peek(0)) {
+                    self.frames.last_mut().unwrap().ip = *target;
+                }
+            }
+            Op::JumpIfTrue(target) => {
+                if interpreter::is_truthy(self.peek(0)) {
+                    self.frames.last_mut().unwrap().ip = *target;
+                }
+            }
+
+            Op::Call(argc) => self.call_value(host, *argc, span)?,
+            Op::Closure(index) => {
+                let function = Rc::clone(&chunk.functions[*index]);
+                let upvalues = function.upvalues.iter().map(|upvalue| {
+                    if upvalue.is_local {
+                        self.capture_upvalue(base + upvalue.index)
+                    } else {
+                        Rc::clone(&closure.upvalues[upvalue.index])
+                    }
+                }).collect();
+                self.stack.push(LiteralType::Callable(Callable::VmClosure(Rc::new(Closure {
+                    function,
+                    upvalues,
+                }))));
+            }
+            Op::CloseUpvalue => {
+                self.close_upvalues(self.stack.len() - 1);
+                self.pop();
+            }
+            Op::Return => {
+                let value = self.pop();
+                let frame = self.frames.pop().unwrap();
+                self.handlers.retain(|h| h.frames <= self.frames.len());
+                self.iterators.truncate(frame.iterators);
+                self.close_upvalues(frame.base);
+                self.stack.truncate(frame.base);
+                if self.frames.len() == depth {
+                    return Ok(Some(value));
+                }
+                self.stack.push(value);
+            }
+
+            Op::Class(index) => self.stack.push(LiteralType::Callable(Callable::VmClass(Rc::new(VmClass {
+                name : chunk.names[*index].to_string(),
+                methods : RefCell::new(HashMap::new()),
+            })))),
+            Op::Inherit => {
+                let class = self.pop();
+                let (LiteralType::Callable(Callable::VmClass(super_class)), LiteralType::Callable(Callable::VmClass(class))) = (self.peek(0), &class) else {
+                    return Err(self.error(span, "Superclass must be a class"));
+                };
+                let inherited = super_class.methods.borrow().clone();
+                class.methods.borrow_mut().extend(inherited);
+            }
+            Op::Method(index) => {
+                let method = self.pop();
+                let (LiteralType::Callable(Callable::VmClass(class)), LiteralType::Callable(Callable::VmClosure(method))) = (self.peek(0), method) else {
+                    unreachable!("methods are compiled right after their class");
+                };
+                class.methods.borrow_mut().insert(chunk.names[*index].clone(), method);
+            }
+
+            Op::List(count) => {
+                let elements = self.stack.split_off(self.stack.len() - count);
+                self.stack.push(LiteralType::List(Rc::new(RefCell::new(elements))));
+            }
+            Op::Map(count) => {
+                let entries = self.stack.split_off(self.stack.len() - 2 * count);
+                let map = Rc::new(RefCell::new(LoxMap::new()));
+                for pair in entries.chunks(2) {
+                    let key = match self.hashed_key(host, &map, &pair[0], span)? {
+                        Some(key) => key,
+                        None => MapKey::from_value(&pair[0]).map_err(|message| self.error(span, message))?,
+                    };
+                    map.borrow_mut().insert(key, pair[1].clone());
+                }
+                self.stack.push(LiteralType::Map(map));
+            }
+
+            Op::Throw => {
+                let value = self.pop();
+                return Err(Exit::Throw(Box::new(Thrown {
+                    value,
+                    keyword : token(span, "throw"),
+                    trace : self.trace(),
+                })));
+            }
+            Op::Rethrow => return Err(self.set_aside.pop().unwrap()),
+            Op::PushHandler { target, rethrow } => {
+                let handler = Handler {
+                    frames : self.frames.len(),
+                    stack : self.stack.len(),
+                    iterators : self.iterators.len(),
+                    set_aside : self.set_aside.len(),
+                    target : *target,
+                    rethrow : *rethrow,
+                };
+                self.handlers.push(handler);
+            }
+            Op::PopHandler => {
+                self.handlers.pop();
+            }
+
+            Op::IterStart => {
+                let iterable = self.pop();
+                let iterator = self.iterator(host, iterable, span)?;
+                self.iterators.push(iterator);
+            }
+            Op::IterNext(exit) => match self.next(host, span)? {
+                Some(value) => self.stack.push(value),
+                None => self.frames.last_mut().unwrap().ip = *exit,
+            },
+            Op::PopIter => {
+                self.iterators.pop();
+            }
+        }
+        Ok(None)
+    }
+}
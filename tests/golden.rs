@@ -0,0 +1,130 @@
+// ? Golden-file tests: every `tests/**/*.lox` script is run and its output
+// ? compared against the annotations in its comments:
+// ?   // expect: <line>                 a line the script prints
+// ?   // expect runtime error: <msg>    the runtime error it stops with, on that line
+// ?   // [line N] Error...              a static error, as `Diagnostic` displays it
+// ?   // Error...                       the same, on the annotated line
+// ? Each script runs on both backends, which must agree with the annotations
+
+use std::cell::RefCell;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crafting_interpreters::{Backend, Lox, STACK_SIZE};
+
+// * Cloneable writer so the output can be read back once the interpreter has it
+#[derive(Clone, Default)]
+struct Captured (Rc<RefCell<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write (&mut self, buf : &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush (&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Outcome {
+    output : Vec<String>,
+    diagnostics : Vec<String>,
+    // * line and message
+    runtime_error : Option<(usize, String)>,
+}
+
+fn expectations (source : &str) -> Outcome {
+    let mut expected = Outcome::default();
+    for (i, line) in source.lines().enumerate() {
+        let Some((_, comment)) = line.split_once("// ") else { continue };
+        if let Some(value) = comment.strip_prefix("expect: ") {
+            expected.output.push(value.to_string());
+        } else if let Some(message) = comment.strip_prefix("expect runtime error: ") {
+            expected.runtime_error = Some((i + 1, message.to_string()));
+        } else if comment.starts_with("[line ") {
+            expected.diagnostics.push(comment.to_string());
+        } else if comment.starts_with("Error") {
+            expected.diagnostics.push(format!("[line {}] {}", i + 1, comment));
+        }
+    }
+    expected
+}
+
+fn run (source : &str, backend : Backend) -> Outcome {
+    let output = Captured::default();
+    let mut lox = Lox::with_io(Box::new(output.clone()), Box::new(std::io::empty()));
+    lox.backend = backend;
+    // scripts must not depend on the machine they run on
+    lox.interpreter().allow_io = false;
+    let result = lox.run(source);
+
+    let printed = String::from_utf8(output.0.borrow().clone()).unwrap();
+    Outcome {
+        output : printed.lines().map(str::to_string).collect(),
+        diagnostics : result.diagnostics.iter().map(|d| d.to_string()).collect(),
+        runtime_error : result.runtime_error.map(|e| (e.token.span.line as usize, e.message)),
+    }
+}
+
+fn scripts (dir : &Path, found : &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            scripts(&path, found);
+        } else if path.extension().is_some_and(|e| e == "lox") {
+            found.push(path);
+        }
+    }
+}
+
+// * Side by side listing of the lines that differ
+fn diff (what : &str, expected : &[String], actual : &[String]) -> String {
+    let mut report = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        let (e, a) = (expected.get(i), actual.get(i));
+        if e != a {
+            report.push_str(&format!("  {} #{}: expected {:?}, got {:?}\n", what, i + 1, e, a));
+        }
+    }
+    report
+}
+
+#[test]
+fn golden_scripts () {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let mut found = vec![];
+    scripts(&root, &mut found);
+    found.sort();
+    assert!(!found.is_empty(), "no .lox scripts under {}", root.display());
+
+    let mut failures = vec![];
+    for path in &found {
+        let source = fs::read_to_string(path).unwrap();
+        let expected = expectations(&source);
+        for backend in [Backend::TreeWalk, Backend::Vm] {
+            // as deep a stack as the binary has, for scripts that recurse a lot
+            let script = source.clone();
+            let actual = std::thread::Builder::new()
+                .stack_size(STACK_SIZE)
+                .spawn(move || run(&script, backend))
+                .unwrap()
+                .join()
+                .unwrap();
+            if expected == actual {
+                continue;
+            }
+            let mut report = format!("{} ({:?})\n", path.strip_prefix(&root).unwrap().display(), backend);
+            report.push_str(&diff("output", &expected.output, &actual.output));
+            report.push_str(&diff("diagnostic", &expected.diagnostics, &actual.diagnostics));
+            if expected.runtime_error != actual.runtime_error {
+                report.push_str(&format!("  runtime error: expected {:?}, got {:?}\n", expected.runtime_error, actual.runtime_error));
+            }
+            failures.push(report);
+        }
+    }
+
+    assert!(failures.is_empty(), "{} of {} runs failed:\n{}", failures.len(), 2 * found.len(), failures.concat());
+}